@@ -5,9 +5,11 @@
 //! - Master ist der Node mit höchster `node_id` unter allen aktiven Heartbeats
 //! - Schutzfunktion: Nur Master darf `sync:group:*` schreiben
 
+use std::collections::HashMap;
+
 use anyhow::{bail, Result};
-use redis::{Commands, Connection};
-use sensor_redis::{write_sync_group, SynchronizedGroup};
+use redis::{Commands, Connection, Value};
+use sensor_redis::{scan_keys, sync_group_key, time_sync_state_key, SynchronizedGroup, TimeSyncState};
 
 /// Baue den Heartbeat-Key für einen Node.
 pub fn heartbeat_key(node_id: &str) -> String {
@@ -23,8 +25,12 @@ pub fn send_heartbeat(con: &mut Connection, node_id: &str, ttl_seconds: usize) -
 }
 
 /// Bestimme aktuelle Master-Node-ID durch Auswahl des höchsten aktiven Heartbeat-Schlüssels.
+///
+/// Iteriert die Heartbeat-Keys über `SCAN` statt über das blockierende
+/// `KEYS`, damit die Election auch bei großem Keyspace den Redis-Server
+/// nicht für die Dauer des Aufrufs stoppt.
 pub fn current_master(con: &mut Connection) -> Result<Option<String>> {
-    let keys: Vec<String> = con.keys("election:bully:hb:*")?;
+    let keys = scan_keys(con, "election:bully:hb:*", 100)?;
     if keys.is_empty() {
         return Ok(None);
     }
@@ -46,17 +52,109 @@ pub fn is_master(con: &mut Connection, node_id: &str) -> Result<bool> {
     Ok(matches!(master.as_deref(), Some(id) if id == node_id))
 }
 
-/// Schutzfunktion: schreibe eine synchronisierte Gruppe nur, wenn `node_id` Master ist.
+/// Sammelt eine neu gebildete synchronisierte Gruppe und alle seit dem
+/// letzten Flush veränderten `TimeSyncState`s, um sie atomar in einer
+/// einzigen `MULTI`/`EXEC`-Transaktion zu veröffentlichen. Ohne dies schreibt
+/// ein Master die Gruppe und jeden Sensor-Zustand über unabhängige `SET`s,
+/// sodass ein Crash mittendrin Gruppe und Filterzustände inkonsistent
+/// zurücklässt und zwischen den Schreibvorgängen eine Neuwahl stattfinden
+/// könnte, die einen anderen Node zum Master macht.
+#[derive(Debug, Default)]
+pub struct SyncCommit {
+    group: Option<(String, SynchronizedGroup)>,
+    dirty_states: HashMap<String, TimeSyncState>,
+}
+
+impl SyncCommit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Markiere eine neu gebildete Gruppe zur Veröffentlichung.
+    pub fn set_group(&mut self, group_id: impl Into<String>, group: SynchronizedGroup) {
+        self.group = Some((group_id.into(), group));
+    }
+
+    /// Markiere den `TimeSyncState` eines Sensors als verändert seit dem
+    /// letzten Flush.
+    pub fn mark_dirty(&mut self, sensor_id: impl Into<String>, state: TimeSyncState) {
+        self.dirty_states.insert(sensor_id.into(), state);
+    }
+
+    /// `true`, wenn weder eine Gruppe noch ein geänderter Zustand aussteht.
+    pub fn is_empty(&self) -> bool {
+        self.group.is_none() && self.dirty_states.is_empty()
+    }
+
+    /// Schreibe Gruppe und alle dirty states atomar in einer
+    /// `MULTI`/`EXEC`-Transaktion, geschützt durch ein `WATCH` auf den
+    /// Heartbeat-Key von `node_id`: ändert sich dieser Heartbeat zwischen
+    /// `WATCH` und `EXEC` (z. B. weil `node_id` zwischenzeitlich die
+    /// Mastership verloren hat), bricht `EXEC` ab und keiner der
+    /// Schreibvorgänge wird wirksam. Bei Erfolg werden die dirty flags über
+    /// `post_flush` geleert; bei Fehlschlag bleibt der Commit-Zustand
+    /// erhalten, damit ein erneuter Versuch dieselben Daten noch einmal
+    /// schreiben kann.
+    pub fn flush(&mut self, con: &mut Connection, node_id: &str) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if !is_master(con, node_id)? {
+            bail!("not master: node '{}' cannot flush SyncCommit", node_id);
+        }
+
+        let hb_key = heartbeat_key(node_id);
+        redis::cmd("WATCH").arg(&hb_key).query::<()>(con)?;
+
+        // Erneute Prüfung nach WATCH: verhindert, dass wir eine Transaktion
+        // aufbauen, die ohnehin sofort verworfen würde.
+        if !is_master(con, node_id)? {
+            redis::cmd("UNWATCH").query::<()>(con)?;
+            bail!("not master: node '{}' lost mastership before commit", node_id);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if let Some((group_id, group)) = &self.group {
+            let json_string = serde_json::to_string(group)?;
+            pipe.cmd("SET").arg(sync_group_key(group_id)).arg(json_string).ignore();
+        }
+        for (sensor_id, state) in &self.dirty_states {
+            let json_string = serde_json::to_string(state)?;
+            pipe.cmd("SET").arg(time_sync_state_key(sensor_id)).arg(json_string).ignore();
+        }
+
+        let result: Value = pipe.query(con)?;
+        if matches!(result, Value::Nil) {
+            bail!(
+                "not master: node '{}' lost mastership during commit (transaction aborted)",
+                node_id
+            );
+        }
+
+        self.post_flush();
+        Ok(())
+    }
+
+    /// Leere die gesammelten dirty flags nach einem erfolgreichen `flush`.
+    pub fn post_flush(&mut self) {
+        self.group = None;
+        self.dirty_states.clear();
+    }
+}
+
+/// Schutzfunktion: schreibe eine synchronisierte Gruppe nur, wenn `node_id`
+/// Master ist. Geht intern über [`SyncCommit`], sodass die Gruppe atomar
+/// geschrieben wird (hier ohne begleitende `TimeSyncState`-Änderungen).
 pub fn write_sync_group_if_master(
     con: &mut Connection,
     node_id: &str,
     group_id: &str,
     group: &SynchronizedGroup,
 ) -> Result<()> {
-    if !is_master(con, node_id)? {
-        bail!("not master: node '{}' cannot write sync:group:*", node_id);
-    }
-    write_sync_group(con, group_id, group)
+    let mut commit = SyncCommit::new();
+    commit.set_group(group_id, group.clone());
+    commit.flush(con, node_id)
 }
 
 #[cfg(test)]
@@ -92,4 +190,71 @@ mod tests {
         assert!(is_master(&mut con, "node-3").unwrap());
         assert!(!is_master(&mut con, "node-2").unwrap());
     }
+
+    #[test]
+    fn sync_commit_starts_empty() {
+        let commit = SyncCommit::new();
+        assert!(commit.is_empty());
+    }
+
+    #[test]
+    fn sync_commit_set_group_and_mark_dirty_clear_is_empty() {
+        let mut commit = SyncCommit::new();
+        commit.set_group("g:1", SynchronizedGroup { t_global: 1.0, members: vec![] });
+        assert!(!commit.is_empty());
+
+        let mut commit2 = SyncCommit::new();
+        commit2.mark_dirty("sensor-1", TimeSyncState::default());
+        assert!(!commit2.is_empty());
+    }
+
+    #[test]
+    fn sync_commit_post_flush_resets_to_empty() {
+        let mut commit = SyncCommit::new();
+        commit.set_group("g:1", SynchronizedGroup { t_global: 1.0, members: vec![] });
+        commit.mark_dirty("sensor-1", TimeSyncState::default());
+        commit.post_flush();
+        assert!(commit.is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn flush_writes_group_and_dirty_states_atomically() {
+        flush();
+        let mut con = get_con();
+        send_heartbeat(&mut con, "node-1", 10).unwrap();
+
+        let mut commit = SyncCommit::new();
+        commit.set_group(
+            "g:1",
+            SynchronizedGroup {
+                t_global: 42.0,
+                members: vec![],
+            },
+        );
+        commit.mark_dirty("sensor-1", TimeSyncState { offset_mean: 0.1, offset_var: 0.01, drift: 1.0, ..Default::default() });
+
+        commit.flush(&mut con, "node-1").unwrap();
+        assert!(commit.is_empty());
+
+        let group = sensor_redis::read_sync_group(&mut con, "g:1").unwrap();
+        assert_eq!(group.t_global, 42.0);
+        let state = sensor_redis::read_time_sync_state(&mut con, "sensor-1").unwrap();
+        assert_eq!(state.offset_mean, 0.1);
+    }
+
+    #[test]
+    #[ignore]
+    fn flush_rejects_when_not_master() {
+        flush();
+        let mut con = get_con();
+        send_heartbeat(&mut con, "node-2", 10).unwrap();
+
+        let mut commit = SyncCommit::new();
+        commit.mark_dirty("sensor-1", TimeSyncState::default());
+        let result = commit.flush(&mut con, "node-1");
+        assert!(result.is_err());
+        // Commit-Zustand bleibt erhalten, damit ein erneuter Versuch möglich ist.
+        assert!(!commit.is_empty());
+    }
 }
@@ -2,15 +2,21 @@
 //!
 //! Python-Bindings für die probabilistische Synchronisation.
 //! Exponiert `SyncEngine` mit `step()` → gibt synchronisierte Gruppen
-//! als Python-freundliche Strukturen zurück.
+//! als Python-freundliche Strukturen zurück. `run()` bietet daneben einen
+//! ereignisgetriebenen Dauerbetrieb auf Basis von Redis-Keyspace-Notifications.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
-use redis::Client;
-use sensor_election::{is_master, send_heartbeat, write_sync_group_if_master};
-use sensor_redis::{get_all_raw_observations, read_time_sync_state, SynchronizedGroup};
-use sensor_sync::{group_observations_probabilistically, TimeOffsetModel};
-use std::collections::HashMap;
+use redis::{Client, Connection};
+use sensor_election::{is_master, send_heartbeat, SyncCommit};
+use sensor_redis::{get_all_raw_observations, read_many_time_sync_states, SynchronizedGroup, TimeSyncState};
+use sensor_sync::{
+    group_observations_probabilistically, KalmanUpdateOutcome, TimeOffset, TimeOffsetModel,
+    DEFAULT_NIS_GATE,
+};
 
 #[pyclass]
 struct SyncEngine {
@@ -19,6 +25,58 @@ struct SyncEngine {
     heartbeat_ttl: usize,
 }
 
+/// Flags, die `run()` für seine `__keyevent@*__:set`-Subscription mindestens
+/// braucht: `K` (Keyspace-Events), `E` (Keyevent-Events), `A` (alle
+/// Befehlsklassen, deckt u. a. die für uns relevanten `set`-Events ab).
+const REQUIRED_NOTIFY_FLAGS: &str = "KEA";
+
+/// Aktiviere die für `run()` benötigten Keyspace-Notifications, ohne die
+/// server-weite Einstellung zu überschreiben: liest den aktuellen Wert,
+/// ergänzt nur fehlende Flags und schreibt das Ergebnis zurück. Gibt
+/// `Some(vorheriger_wert)` zurück, falls tatsächlich etwas geändert wurde
+/// (damit der Aufrufer die ursprüngliche Einstellung später wiederherstellen
+/// kann), sonst `None`.
+fn enable_keyspace_notifications(con: &mut Connection) -> PyResult<Option<String>> {
+    let current: String = redis::cmd("CONFIG")
+        .arg("GET")
+        .arg("notify-keyspace-events")
+        .query::<Vec<String>>(con)
+        .map_err(|e| PyRuntimeError::new_err(format!("reading notify-keyspace-events failed: {e}")))?
+        .into_iter()
+        .nth(1)
+        .unwrap_or_default();
+
+    if REQUIRED_NOTIFY_FLAGS.chars().all(|c| current.contains(c)) {
+        return Ok(None);
+    }
+
+    let mut merged = current.clone();
+    for c in REQUIRED_NOTIFY_FLAGS.chars() {
+        if !merged.contains(c) {
+            merged.push(c);
+        }
+    }
+    redis::cmd("CONFIG")
+        .arg("SET")
+        .arg("notify-keyspace-events")
+        .arg(&merged)
+        .query::<()>(con)
+        .map_err(|e| PyRuntimeError::new_err(format!("enabling keyspace notifications failed: {e}")))?;
+    Ok(Some(current))
+}
+
+/// Stelle den vor `enable_keyspace_notifications` geltenden Wert wieder her,
+/// damit `run()` eine geteilte Redis-Instanz nicht dauerhaft umkonfiguriert.
+fn restore_keyspace_notifications(con: &mut Connection, previous: &str) -> PyResult<()> {
+    redis::cmd("CONFIG")
+        .arg("SET")
+        .arg("notify-keyspace-events")
+        .arg(previous)
+        .query::<()>(con)
+        .map_err(|e| PyRuntimeError::new_err(format!("restoring notify-keyspace-events failed: {e}")))?;
+    Ok(())
+}
+
 fn to_py_group(py: Python<'_>, group: &SynchronizedGroup) -> PyResult<Py<PyAny>> {
     let out = pyo3::types::PyDict::new(py);
     out.set_item("t_global", group.t_global)?;
@@ -47,47 +105,277 @@ impl SyncEngine {
     /// Führe einen Synchronisationsschritt aus und liefere eine Liste von Gruppen.
     /// Jede Gruppe ist ein Dict mit `t_global: float` und `members: List[Dict]`.
     fn step(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
-        // Redis verbinden
         let client = Client::open(self.redis_url.as_str())
             .map_err(|e| PyRuntimeError::new_err(format!("redis client error: {e}")))?;
         let mut con = client
             .get_connection()
             .map_err(|e| PyRuntimeError::new_err(format!("redis connection error: {e}")))?;
 
-        // Heartbeat senden
         send_heartbeat(&mut con, &self.node_id, self.heartbeat_ttl)
             .map_err(|e| PyRuntimeError::new_err(format!("heartbeat error: {e}")))?;
 
-        // Rohbeobachtungen laden
-        let observations = get_all_raw_observations(&mut con)
+        self.do_step(py, &mut con)
+    }
+
+    /// Ereignisgetriebener Dauerbetrieb: abonniert Redis-Keyspace-Notifications
+    /// auf `obs:*`-Schreibvorgänge und verarbeitet neue Beobachtungen, statt
+    /// den gesamten Keyspace in jedem Zyklus erneut abzuscannen. Beobachtungen,
+    /// die innerhalb von `coalesce_window_ms` nacheinander eintreffen, werden
+    /// zu einer einzigen Gruppierungsrunde zusammengefasst. Heartbeats laufen
+    /// unabhängig auf einem eigenen Timer (halbe `heartbeat_ttl`), und alle
+    /// `scan_fallback_interval_ms` wird zusätzlich ein `SCAN`-Sweep erzwungen,
+    /// damit Beobachtungen, die vor dem Abonnement geschrieben wurden oder
+    /// deren Notification verloren ging, trotzdem verarbeitet werden.
+    ///
+    /// `max_iterations` begrenzt die Anzahl der Poll-Zyklen (v. a. für Tests);
+    /// `None` läuft, bis der Prozess beendet oder `pubsub`-Fehler auftreten.
+    #[pyo3(signature = (coalesce_window_ms=200, scan_fallback_interval_ms=5000, max_iterations=None))]
+    fn run(
+        &self,
+        py_ref: Python<'_>,
+        coalesce_window_ms: u64,
+        scan_fallback_interval_ms: u64,
+        max_iterations: Option<u64>,
+    ) -> PyResult<()> {
+        let client = Client::open(self.redis_url.as_str())
+            .map_err(|e| PyRuntimeError::new_err(format!("redis client error: {e}")))?;
+
+        // Eigene Verbindung fürs Pub/Sub, getrennt von der für Heartbeats und
+        // Schreibvorgänge verwendeten Verbindung.
+        let mut notify_con = client
+            .get_connection()
+            .map_err(|e| PyRuntimeError::new_err(format!("redis connection error: {e}")))?;
+        let previous_notify_config = enable_keyspace_notifications(&mut notify_con)?;
+
+        // Poll-Timeout so kurz wie das kürzere der beiden Fenster: lässt den
+        // Heartbeat- und Sweep-Timer zeitnah genug prüfen, ohne aktiv zu spinnen.
+        let poll_timeout = Duration::from_millis(coalesce_window_ms.min(scan_fallback_interval_ms).max(1));
+        notify_con
+            .set_read_timeout(Some(poll_timeout))
+            .map_err(|e| PyRuntimeError::new_err(format!("set_read_timeout error: {e}")))?;
+        let mut pubsub = notify_con.as_pubsub();
+        pubsub
+            .psubscribe("__keyevent@*__:set")
+            .map_err(|e| PyRuntimeError::new_err(format!("psubscribe error: {e}")))?;
+
+        let mut work_con = client
+            .get_connection()
+            .map_err(|e| PyRuntimeError::new_err(format!("redis connection error: {e}")))?;
+
+        let heartbeat_interval = Duration::from_secs((self.heartbeat_ttl.max(1) / 2).max(1) as u64);
+        let scan_interval = Duration::from_millis(scan_fallback_interval_ms.max(1));
+        let coalesce_window = Duration::from_millis(coalesce_window_ms.max(1));
+
+        let mut last_heartbeat = Instant::now() - heartbeat_interval;
+        let mut last_sweep = Instant::now() - scan_interval;
+        let mut burst_started: Option<Instant> = None;
+
+        let mut iterations: u64 = 0;
+        let result = (|| -> PyResult<()> {
+            loop {
+                // Blockierendes Polling (Heartbeat-Schreibvorgang, Pub/Sub-Warten)
+                // läuft mit freigegebenem GIL, damit andere Python-Threads und
+                // ausstehende Signale (z. B. Ctrl+C) währenddessen nicht einfrieren.
+                let (burst_ready, sweep_due) = py_ref.allow_threads(|| -> PyResult<(bool, bool)> {
+                    if last_heartbeat.elapsed() >= heartbeat_interval {
+                        send_heartbeat(&mut work_con, &self.node_id, self.heartbeat_ttl)
+                            .map_err(|e| PyRuntimeError::new_err(format!("heartbeat error: {e}")))?;
+                        last_heartbeat = Instant::now();
+                    }
+
+                    match pubsub.get_message() {
+                        Ok(msg) => {
+                            let key: String = msg.get_payload().unwrap_or_default();
+                            if key.starts_with("obs:") {
+                                burst_started.get_or_insert_with(Instant::now);
+                            }
+                        }
+                        Err(e) if e.is_timeout() => {
+                            // Kein Signal innerhalb des Poll-Fensters: unten prüfen, ob
+                            // ein Burst reif ist oder ein Sweep fällig ist.
+                        }
+                        Err(e) => return Err(PyRuntimeError::new_err(format!("pubsub error: {e}"))),
+                    }
+
+                    let burst_ready = burst_started
+                        .map(|start| start.elapsed() >= coalesce_window)
+                        .unwrap_or(false);
+                    let sweep_due = last_sweep.elapsed() >= scan_interval;
+                    Ok((burst_ready, sweep_due))
+                })?;
+
+                if burst_ready || sweep_due {
+                    // Benötigt das GIL, da `do_step` Python-Objekte erzeugt.
+                    self.do_step(py_ref, &mut work_con)?;
+                    burst_started = None;
+                }
+                if sweep_due {
+                    last_sweep = Instant::now();
+                }
+
+                iterations += 1;
+                if let Some(max) = max_iterations {
+                    if iterations >= max {
+                        break;
+                    }
+                }
+            }
+            Ok(())
+        })();
+
+        if let Some(previous) = previous_notify_config {
+            // `notify_con` ist per `psubscribe` dauerhaft im Subscriber-Modus;
+            // Redis lehnt ein `CONFIG SET` auf einer solchen Verbindung ab
+            // (RESP2). `work_con` wurde nie abonniert und eignet sich daher
+            // für die Wiederherstellung. Ein Fehlschlag hier darf außerdem
+            // keinen andernfalls erfolgreichen Lauf nachträglich als Fehler
+            // melden; nur wenn die Schleife selbst erfolgreich war, wird der
+            // Restore-Fehler nach außen durchgereicht.
+            let restore_result = restore_keyspace_notifications(&mut work_con, &previous);
+            if result.is_ok() {
+                restore_result?;
+            }
+        }
+
+        result
+    }
+
+    /// Liefert `true`, falls innerhalb von `timeout_ms` mindestens eine
+    /// `obs:*`-Keyspace-Notification eintraf, sonst `false`. Erlaubt es einem
+    /// externen Event-Loop (z. B. eine `asyncio`-Schleife), die
+    /// Pub/Sub-Bereitschaft periodisch selbst abzufragen, statt zwingend den
+    /// blockierenden `run()`-Modus zu verwenden.
+    fn pubsub_ready(&self, timeout_ms: u64) -> PyResult<bool> {
+        let client = Client::open(self.redis_url.as_str())
+            .map_err(|e| PyRuntimeError::new_err(format!("redis client error: {e}")))?;
+        let mut con = client
+            .get_connection()
+            .map_err(|e| PyRuntimeError::new_err(format!("redis connection error: {e}")))?;
+        con.set_read_timeout(Some(Duration::from_millis(timeout_ms.max(1))))
+            .map_err(|e| PyRuntimeError::new_err(format!("set_read_timeout error: {e}")))?;
+        let mut pubsub = con.as_pubsub();
+        pubsub
+            .psubscribe("__keyevent@*__:set")
+            .map_err(|e| PyRuntimeError::new_err(format!("psubscribe error: {e}")))?;
+
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms.max(1));
+        loop {
+            match pubsub.get_message() {
+                Ok(msg) => {
+                    let key: String = msg.get_payload().unwrap_or_default();
+                    if key.starts_with("obs:") {
+                        return Ok(true);
+                    }
+                }
+                Err(e) if e.is_timeout() => return Ok(false),
+                Err(e) => return Err(PyRuntimeError::new_err(format!("pubsub error: {e}"))),
+            }
+            // Andere `set`-Events: bis zum Ablauf von `timeout_ms` weiter warten.
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            pubsub
+                .set_read_timeout(Some(remaining))
+                .map_err(|e| PyRuntimeError::new_err(format!("set_read_timeout error: {e}")))?;
+        }
+    }
+}
+
+impl SyncEngine {
+    /// Gemeinsamer Synchronisationsschritt für `step()` und `run()`: liest
+    /// alle aktuellen Rohbeobachtungen, faltet jede einzeln über
+    /// `TimeOffset::kalman_update_gated` in den gespeicherten Sensor-Zustand
+    /// ein (Ausreißer werden dabei per NIS-Gate verworfen, bevor sie in die
+    /// Gruppe einfließen), bildet daraus eine Gruppe und schreibt sie
+    /// zusammen mit allen veränderten `TimeSyncState`s atomar über einen
+    /// `SyncCommit`, falls dieser Node Master ist. Sendet selbst keinen
+    /// Heartbeat, da `step()` und `run()` dafür ihre eigenen Timer verwenden.
+    fn do_step(&self, py: Python<'_>, con: &mut Connection) -> PyResult<Vec<Py<PyAny>>> {
+        let observations = get_all_raw_observations(con)
             .map_err(|e| PyRuntimeError::new_err(format!("read observations error: {e}")))?;
 
-        if observations.is_empty() { return Ok(vec![]); }
+        if observations.is_empty() {
+            return Ok(vec![]);
+        }
 
-        // TimeSyncState je Sensor cachen
-        let mut cache: HashMap<String, TimeOffsetModel> = HashMap::new();
-        let mut models = Vec::with_capacity(observations.len());
+        // TimeSyncState aller beteiligten Sensoren in einem MGET statt pro
+        // Sensor eine eigene GET-Anfrage laden.
+        let sensor_ids: Vec<&str> = {
+            let mut seen = std::collections::HashSet::new();
+            observations
+                .iter()
+                .map(|obs| obs.sensor_id.as_str())
+                .filter(|id| seen.insert(*id))
+                .collect()
+        };
+        let states = read_many_time_sync_states(con, &sensor_ids)
+            .map_err(|e| PyRuntimeError::new_err(format!("read time sync states error: {e}")))?;
+        let mut kalman_states: HashMap<String, TimeOffset> = sensor_ids
+            .into_iter()
+            .map(|sensor_id| {
+                let offset = states
+                    .get(sensor_id)
+                    .map(TimeOffset::from)
+                    .unwrap_or_else(TimeOffset::new);
+                (sensor_id.to_string(), offset)
+            })
+            .collect();
+
+        // Provisorische Gruppe über alle Beobachtungen mit dem aktuellen
+        // Kalman-Zustand bilden, nur um einen Referenzwert für das
+        // NIS-Gating jeder einzelnen Beobachtung zu haben.
+        let provisional_models: Vec<TimeOffsetModel> = observations
+            .iter()
+            .map(|obs| to_time_offset_model(&kalman_states[&obs.sensor_id]))
+            .collect();
+        let provisional_group = group_observations_probabilistically(&observations, &provisional_models)
+            .map_err(|e| PyRuntimeError::new_err(format!("grouping error: {e}")))?;
+
+        // Jede Beobachtung einzeln durch das NIS-gegatete Kalman-Update laufen
+        // lassen: Ausreißer werden verworfen, bevor sie in die finale Gruppe
+        // einfließen. Bei ihnen wird nur das laufende NIS-Mittel im Speicher
+        // fortgeschrieben; Offset, Drift und Kovarianz bleiben unverändert, und
+        // `TimeSyncState` speichert das NIS-Mittel nicht.
+        let mut dirty_states: HashMap<String, TimeSyncState> = HashMap::new();
+        let mut accepted_observations = Vec::new();
+        let mut accepted_models = Vec::new();
         for obs in &observations {
-            let entry = cache.entry(obs.sensor_id.clone()).or_insert_with(|| {
-                match read_time_sync_state(&mut con, &obs.sensor_id) {
-                    Ok(state) => TimeOffsetModel::from(state),
-                    Err(_) => TimeOffsetModel { offset_mean: 0.0, offset_var: 0.1, drift: 1.0 },
-                }
-            });
-            models.push(entry.clone());
+            let kalman = kalman_states
+                .get_mut(&obs.sensor_id)
+                .expect("jeder sensor_id wurde oben aus states/Default befüllt");
+            let outcome = kalman.kalman_update_gated(
+                provisional_group.t_global,
+                obs.sigma.powi(2),
+                obs.t_local,
+                DEFAULT_NIS_GATE,
+            );
+            dirty_states.insert(obs.sensor_id.clone(), TimeSyncState::from(&*kalman));
+            if let KalmanUpdateOutcome::Accepted { .. } = outcome {
+                accepted_models.push(to_time_offset_model(kalman));
+                accepted_observations.push(obs.clone());
+            }
         }
 
-        // Eine Gruppe für diesen Batch bilden
-        let group = group_observations_probabilistically(&observations, &models)
+        // Finale Gruppe nur über die vom Gate akzeptierten Beobachtungen bilden.
+        let group = group_observations_probabilistically(&accepted_observations, &accepted_models)
             .map_err(|e| PyRuntimeError::new_err(format!("grouping error: {e}")))?;
 
-        // Schreiben nur wenn Master
-        if is_master(&mut con, &self.node_id)
-            .map_err(|e| PyRuntimeError::new_err(format!("is_master error: {e}")))? {
+        // Schreiben nur wenn Master: Gruppe und alle veränderten
+        // TimeSyncStates atomar über einen einzigen SyncCommit.
+        if is_master(con, &self.node_id)
+            .map_err(|e| PyRuntimeError::new_err(format!("is_master error: {e}")))?
+        {
+            let mut commit = SyncCommit::new();
             // group_id deterministisch aus Zeit ableiten
             let group_id = format!("g:{}", (group.t_global * 1e9).round() as i128);
-            write_sync_group_if_master(&mut con, &self.node_id, &group_id, &group)
-                .map_err(|e| PyRuntimeError::new_err(format!("write group error: {e}")))?;
+            commit.set_group(group_id, group.clone());
+            for (sensor_id, state) in dirty_states {
+                commit.mark_dirty(sensor_id, state);
+            }
+            commit
+                .flush(con, &self.node_id)
+                .map_err(|e| PyRuntimeError::new_err(format!("sync commit error: {e}")))?;
         }
 
         // In Python-Objekt wandeln (Liste von 1 Gruppe aktuell)
@@ -96,6 +384,16 @@ impl SyncEngine {
     }
 }
 
+/// Projiziere den vollen 2-Zustands-Kalman-Filter auf das skalare
+/// `TimeOffsetModel`, das `group_observations_probabilistically` erwartet.
+fn to_time_offset_model(offset: &TimeOffset) -> TimeOffsetModel {
+    TimeOffsetModel {
+        offset_mean: offset.offset_mean,
+        offset_var: offset.offset_variance(),
+        drift: offset.drift,
+    }
+}
+
 #[pymodule]
 fn sensorium(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<SyncEngine>()?;
@@ -2,12 +2,35 @@
 //!
 //! This crate defines the data structures that are stored in Redis
 //! and provides functions for interacting with the Redis database.
+use std::collections::HashMap;
+
 use anyhow::Result;
 use redis::{Commands, Connection};
 use serde::{Deserialize, Serialize};
 
 // --- Data Structures ---
 
+/// Typisierte Nutzlast einer Beobachtung.
+///
+/// `Reference` bildet die ursprüngliche `payload_ref`-Indirektion ab, damit
+/// bestehende Daten unverändert weiterverwendet werden können; die übrigen
+/// Varianten tragen den Messwert direkt, ohne dass Konsumenten ihn
+/// außerhalb des Datensatzes auflösen und erraten müssen.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Payload {
+    Integer(i64),
+    Float(f64),
+    Blob(Vec<u8>),
+    Reference(String),
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Payload::Reference(String::new())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct RawObservation {
     pub sensor_id: String,
@@ -15,13 +38,73 @@ pub struct RawObservation {
     pub t_local: f64,
     pub sigma: f64,
     pub payload_ref: String,
+    /// Typisierte Nutzlast; fehlt sie beim Deserialisieren älterer Daten,
+    /// bleibt sie das leere `Payload::Reference("")`, und
+    /// `effective_payload` liefert dann `Payload::Reference(payload_ref)`.
+    #[serde(default)]
+    pub payload: Payload,
+    /// Ende des Beobachtungsintervalls (Sekunden). `None` bedeutet eine
+    /// Instant-Beobachtung (ein einzelner Zeitpunkt `t_local`); `Some(end)`
+    /// macht daraus eine Intervall-Beobachtung `[t_local, end]`.
+    #[serde(default)]
+    pub t_local_end: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+impl Default for RawObservation {
+    fn default() -> Self {
+        Self {
+            sensor_id: String::new(),
+            sensor_type: String::new(),
+            t_local: 0.0,
+            sigma: 0.0,
+            payload_ref: String::new(),
+            payload: Payload::default(),
+            t_local_end: None,
+        }
+    }
+}
+
+impl RawObservation {
+    /// Die effektive typisierte Nutzlast: `payload`, falls explizit gesetzt
+    /// (irgendetwas außer dem leeren Default), sonst aus `payload_ref`
+    /// abgeleitet.
+    pub fn effective_payload(&self) -> Payload {
+        match &self.payload {
+            Payload::Reference(r) if r.is_empty() && !self.payload_ref.is_empty() => {
+                Payload::Reference(self.payload_ref.clone())
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// `true`, wenn dies eine Intervall-Beobachtung ist (`t_local_end` gesetzt).
+    pub fn is_interval(&self) -> bool {
+        self.t_local_end.is_some()
+    }
+
+    /// Prüft, ob ein Zeitpunkt `t` in den Sample-Zeitraum dieser Beobachtung
+    /// fällt: bei einer Instant-Beobachtung nur exakt `t_local`, bei einer
+    /// Intervall-Beobachtung jeder Punkt in `[t_local, t_local_end]`.
+    pub fn covers(&self, t: f64) -> bool {
+        match self.t_local_end {
+            Some(end) => t >= self.t_local && t <= end,
+            None => (t - self.t_local).abs() < f64::EPSILON,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
 pub struct TimeSyncState {
     pub offset_mean: f64,
     pub offset_var: f64,
     pub drift: f64,
+    /// Drift-Varianz `P[1][1]` des 2-Zustands-Kalman-Filters. `0.0` bei
+    /// älteren, rein skalaren Zuständen ohne mitgeschätzte Drift.
+    #[serde(default)]
+    pub drift_var: f64,
+    /// Kovarianz-Kopplung `P[0][1] == P[1][0]` zwischen Offset und Drift.
+    #[serde(default)]
+    pub offset_drift_covariance: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -72,6 +155,72 @@ fn read_struct<T: for<'de> Deserialize<'de>>(
     Ok(value)
 }
 
+// --- Cursor-based Key Iteration ---
+
+/// Sammle alle Redis-Keys, die zu `pattern` passen, über `SCAN` statt über
+/// das blockierende `KEYS`, das bei großem Keyspace den gesamten Server für
+/// die Dauer des Aufrufs blockiert. `count` ist ein Hinweis an Redis, wie
+/// viele Keys pro Scan-Runde zurückgegeben werden sollen (keine Garantie).
+pub fn scan_keys(con: &mut Connection, pattern: &str, count: usize) -> Result<Vec<String>> {
+    let mut cursor: u64 = 0;
+    let mut keys = Vec::new();
+    loop {
+        let (next_cursor, mut page): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(count)
+            .query(con)?;
+        keys.append(&mut page);
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+    Ok(keys)
+}
+
+/// Wie [`get_all_raw_observations`], aber ohne den gesamten Keyspace vorab zu
+/// materialisieren: jede `SCAN`-Seite wird sofort per `MGET` aufgelöst und an
+/// `on_batch` übergeben, sodass der Speicherbedarf auch bei sehr vielen
+/// Beobachtungen auf eine Seite begrenzt bleibt statt mit der Gesamtzahl der
+/// Keys zu wachsen.
+pub fn iter_raw_observations(
+    con: &mut Connection,
+    count: usize,
+    mut on_batch: impl FnMut(Vec<RawObservation>) -> Result<()>,
+) -> Result<()> {
+    let mut cursor: u64 = 0;
+    loop {
+        let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg("obs:*")
+            .arg("COUNT")
+            .arg(count)
+            .query(con)?;
+
+        if !keys.is_empty() {
+            // Zwischen SCAN und MGET kann ein Key ablaufen oder gelöscht
+            // werden; solche Lücken werden stillschweigend übersprungen statt
+            // den gesamten Batch scheitern zu lassen.
+            let values: Vec<Option<String>> = con.get(&keys)?;
+            let mut batch = Vec::with_capacity(values.len());
+            for val in values.into_iter().flatten() {
+                batch.push(serde_json::from_str(&val)?);
+            }
+            on_batch(batch)?;
+        }
+
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+    Ok(())
+}
+
 // --- Read/Write Functions ---
 
 pub fn write_raw_observation(
@@ -115,18 +264,149 @@ pub fn write_sync_group(
 
 pub fn get_all_raw_observations(con: &mut Connection) -> Result<Vec<RawObservation>> {
     let mut observations = Vec::new();
-    let keys: Vec<String> = con.keys("obs:*")?;
+    let keys = scan_keys(con, "obs:*", 100)?;
     if keys.is_empty() {
         return Ok(observations);
     }
-    let values: Vec<String> = con.get(keys)?;
-    for val in values {
+    // Zwischen SCAN und MGET kann ein Key ablaufen oder gelöscht werden;
+    // solche Lücken werden stillschweigend übersprungen.
+    let values: Vec<Option<String>> = con.get(keys)?;
+    for val in values.into_iter().flatten() {
         let obs: RawObservation = serde_json::from_str(&val)?;
         observations.push(obs);
     }
     Ok(observations)
 }
 
+/// Lies den `TimeSyncState` mehrerer Sensoren in einem einzigen `MGET`-Aufruf,
+/// statt pro Sensor eine eigene blockierende `GET`-Anfrage abzusetzen. Fehlt
+/// der Zustand eines Sensors (noch nie geschrieben oder TTL abgelaufen),
+/// fehlt dessen `sensor_id` einfach im Ergebnis statt einen Fehler auszulösen.
+pub fn read_many_time_sync_states(
+    con: &mut Connection,
+    sensor_ids: &[&str],
+) -> Result<HashMap<String, TimeSyncState>> {
+    let mut states = HashMap::new();
+    if sensor_ids.is_empty() {
+        return Ok(states);
+    }
+    let keys: Vec<String> = sensor_ids.iter().map(|id| time_sync_state_key(id)).collect();
+    let values: Vec<Option<String>> = con.get(keys)?;
+    for (sensor_id, value) in sensor_ids.iter().zip(values) {
+        if let Some(json_string) = value {
+            let state: TimeSyncState = serde_json::from_str(&json_string)?;
+            states.insert((*sensor_id).to_string(), state);
+        }
+    }
+    Ok(states)
+}
+
+// --- Batched Writes With Cache ---
+
+/// Richtlinie für den In-Memory-Cache einer [`RedisBatch`] beim Schreiben
+/// eines Werts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheUpdatePolicy {
+    /// Halte den geschriebenen Wert im Cache vor, damit ein nachfolgender
+    /// `cached`-Aufruf Redis nicht erneut kontaktieren muss.
+    Overwrite,
+    /// Entferne einen eventuell vorhandenen Cache-Eintrag für diesen
+    /// Schlüssel (z. B. wenn der geschriebene Wert nicht zwischengespeichert
+    /// werden soll).
+    Remove,
+}
+
+/// Sammelt Schreibvorgänge und führt sie gebündelt in einer einzigen
+/// `MULTI`/`EXEC`-Pipeline aus, statt für jeden Wert eine eigene blockierende
+/// `SET` abzusetzen. Hält zusätzlich einen In-Memory-Cache der zuletzt
+/// geschriebenen Werte vor, den `cached` ohne Redis-Zugriff ausliest.
+/// `SyncEngine::step` in `sensor_python` verwendet `RedisBatch` nicht und
+/// liest die Zustände bei jedem Aufruf per `MGET` neu.
+pub struct RedisBatch {
+    pipe: redis::Pipeline,
+    cache: HashMap<String, String>,
+    pending: usize,
+}
+
+impl RedisBatch {
+    pub fn new() -> Self {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        Self {
+            pipe,
+            cache: HashMap::new(),
+            pending: 0,
+        }
+    }
+
+    /// Reihe einen Schreibvorgang in die Pipeline ein und aktualisiere den
+    /// Cache gemäß `policy`.
+    pub fn write_with_cache<T: Serialize>(
+        &mut self,
+        key: &str,
+        value: &T,
+        policy: CacheUpdatePolicy,
+    ) -> Result<()> {
+        let json_string = serde_json::to_string(value)?;
+        self.pipe.set(key, &json_string).ignore();
+        self.pending += 1;
+        match policy {
+            CacheUpdatePolicy::Overwrite => {
+                self.cache.insert(key.to_string(), json_string);
+            }
+            CacheUpdatePolicy::Remove => {
+                self.cache.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reihe mehrere Schreibvorgänge mit derselben `policy` ein.
+    pub fn extend_with_cache<'a, T, I>(&mut self, items: I, policy: CacheUpdatePolicy) -> Result<()>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item = (&'a str, &'a T)>,
+    {
+        for (key, value) in items {
+            self.write_with_cache(key, value, policy)?;
+        }
+        Ok(())
+    }
+
+    /// Liefere den gecachten Wert für `key`, ohne Redis zu kontaktieren.
+    pub fn cached<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Option<T> {
+        self.cache
+            .get(key)
+            .and_then(|json_string| serde_json::from_str(json_string).ok())
+    }
+
+    /// Anzahl der noch nicht geflushten Schreibvorgänge.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Führe alle bislang eingereihten Schreibvorgänge in einer einzigen
+    /// `MULTI`/`EXEC`-Transaktion aus und setze die Pipeline danach zurück.
+    /// Der In-Memory-Cache bleibt erhalten.
+    pub fn flush(&mut self, con: &mut Connection) -> Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        self.pipe.query::<()>(con)?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.pipe = pipe;
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+impl Default for RedisBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -153,6 +433,67 @@ mod tests {
         assert_eq!(sync_group_key("group-abc"), "sync:group:group-abc");
     }
 
+    #[test]
+    fn test_effective_payload_falls_back_to_payload_ref() {
+        let obs = RawObservation {
+            sensor_id: "sensor-alpha".to_string(),
+            sensor_type: "camera".to_string(),
+            t_local: 1.0,
+            sigma: 0.05,
+            payload_ref: "s3://bucket/img1.jpg".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            obs.effective_payload(),
+            Payload::Reference("s3://bucket/img1.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn test_effective_payload_prefers_explicit_payload() {
+        let obs = RawObservation {
+            sensor_id: "sensor-alpha".to_string(),
+            sensor_type: "camera".to_string(),
+            t_local: 1.0,
+            sigma: 0.05,
+            payload_ref: "s3://bucket/img1.jpg".to_string(),
+            payload: Payload::Integer(42),
+            ..Default::default()
+        };
+        assert_eq!(obs.effective_payload(), Payload::Integer(42));
+    }
+
+    #[test]
+    fn test_instant_observation_covers_only_t_local() {
+        let obs = RawObservation {
+            sensor_id: "s1".to_string(),
+            sensor_type: "test".to_string(),
+            t_local: 5.0,
+            sigma: 0.01,
+            ..Default::default()
+        };
+        assert!(!obs.is_interval());
+        assert!(obs.covers(5.0));
+        assert!(!obs.covers(5.1));
+    }
+
+    #[test]
+    fn test_interval_observation_covers_its_span() {
+        let obs = RawObservation {
+            sensor_id: "s1".to_string(),
+            sensor_type: "test".to_string(),
+            t_local: 5.0,
+            t_local_end: Some(7.0),
+            sigma: 0.01,
+            ..Default::default()
+        };
+        assert!(obs.is_interval());
+        assert!(obs.covers(5.0));
+        assert!(obs.covers(6.0));
+        assert!(obs.covers(7.0));
+        assert!(!obs.covers(7.1));
+    }
+
     #[test]
     #[ignore]
     fn test_raw_observation_io() {
@@ -165,7 +506,7 @@ mod tests {
             t_local: 9876.5432,
             sigma: 0.05,
             payload_ref: "s3://bucket/img1.jpg".to_string(),
-        };
+         ..Default::default() };
 
         assert!(write_raw_observation(&mut con, &obs, 10).is_ok());
 
@@ -178,6 +519,56 @@ mod tests {
         assert!(ttl > 0 && ttl <= 10);
     }
 
+    #[test]
+    #[ignore]
+    fn test_scan_keys_finds_all_matching_keys_across_pages() {
+        flush_db();
+        let mut con = get_redis_connection();
+
+        for i in 0..25 {
+            let obs = RawObservation {
+                sensor_id: format!("sensor-{i}"),
+                sensor_type: "test".to_string(),
+                t_local: i as f64,
+                sigma: 0.01,
+                ..Default::default()
+            };
+            write_raw_observation(&mut con, &obs, 60).unwrap();
+        }
+
+        // COUNT kleiner als die Gesamtzahl der Keys erzwingt mehrere
+        // Scan-Runden.
+        let keys = scan_keys(&mut con, "obs:*", 5).unwrap();
+        assert_eq!(keys.len(), 25);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_iter_raw_observations_streams_all_observations_in_batches() {
+        flush_db();
+        let mut con = get_redis_connection();
+
+        for i in 0..12 {
+            let obs = RawObservation {
+                sensor_id: format!("sensor-{i}"),
+                sensor_type: "test".to_string(),
+                t_local: i as f64,
+                sigma: 0.01,
+                ..Default::default()
+            };
+            write_raw_observation(&mut con, &obs, 60).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        iter_raw_observations(&mut con, 4, |batch| {
+            seen.extend(batch);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(seen.len(), 12);
+    }
+
     #[test]
     #[ignore]
     fn test_time_sync_state_io() {
@@ -188,6 +579,7 @@ mod tests {
             offset_mean: -0.1,
             offset_var: 0.002,
             drift: 1.00001,
+            ..Default::default()
         };
         let sensor_id = "sensor-beta";
 
@@ -217,4 +609,79 @@ mod tests {
         let read_group = read_sync_group(&mut con, group_id).unwrap();
         assert_eq!(read_group, group);
     }
+
+    #[test]
+    fn redis_batch_cache_overwrite_is_readable_without_flush() {
+        let mut batch = RedisBatch::new();
+        let state = TimeSyncState {
+            offset_mean: 0.1,
+            offset_var: 0.01,
+            drift: 1.0,
+            ..Default::default()
+        };
+        let key = time_sync_state_key("sensor-cache");
+        batch.write_with_cache(&key, &state, CacheUpdatePolicy::Overwrite).unwrap();
+        assert_eq!(batch.pending(), 1);
+        assert_eq!(batch.cached::<TimeSyncState>(&key), Some(state));
+    }
+
+    #[test]
+    fn redis_batch_cache_remove_evicts_existing_entry() {
+        let mut batch = RedisBatch::new();
+        let key = time_sync_state_key("sensor-cache");
+        let state = TimeSyncState::default();
+        batch.write_with_cache(&key, &state, CacheUpdatePolicy::Overwrite).unwrap();
+        assert!(batch.cached::<TimeSyncState>(&key).is_some());
+
+        batch.write_with_cache(&key, &state, CacheUpdatePolicy::Remove).unwrap();
+        assert_eq!(batch.cached::<TimeSyncState>(&key), None);
+    }
+
+    #[test]
+    fn redis_batch_extend_with_cache_queues_all_items() {
+        let mut batch = RedisBatch::new();
+        let states: Vec<(String, TimeSyncState)> = vec![
+            (time_sync_state_key("s1"), TimeSyncState::default()),
+            (time_sync_state_key("s2"), TimeSyncState::default()),
+        ];
+        let items: Vec<(&str, &TimeSyncState)> =
+            states.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        batch.extend_with_cache(items, CacheUpdatePolicy::Overwrite).unwrap();
+        assert_eq!(batch.pending(), 2);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_read_many_time_sync_states_via_mget() {
+        flush_db();
+        let mut con = get_redis_connection();
+
+        let s1 = TimeSyncState { offset_mean: 0.1, offset_var: 0.01, drift: 1.0, ..Default::default() };
+        let s2 = TimeSyncState { offset_mean: 0.2, offset_var: 0.02, drift: 1.0001, ..Default::default() };
+        write_time_sync_state(&mut con, "sensor-one", &s1).unwrap();
+        write_time_sync_state(&mut con, "sensor-two", &s2).unwrap();
+
+        let states = read_many_time_sync_states(&mut con, &["sensor-one", "sensor-two", "sensor-missing"]).unwrap();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states.get("sensor-one"), Some(&s1));
+        assert_eq!(states.get("sensor-two"), Some(&s2));
+        assert!(!states.contains_key("sensor-missing"));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redis_batch_flush_writes_all_queued_values() {
+        flush_db();
+        let mut con = get_redis_connection();
+
+        let mut batch = RedisBatch::new();
+        let state = TimeSyncState { offset_mean: 0.5, offset_var: 0.01, drift: 1.0, ..Default::default() };
+        let key = time_sync_state_key("sensor-flush");
+        batch.write_with_cache(&key, &state, CacheUpdatePolicy::Overwrite).unwrap();
+        batch.flush(&mut con).unwrap();
+        assert_eq!(batch.pending(), 0);
+
+        let read_state = read_time_sync_state(&mut con, "sensor-flush").unwrap();
+        assert_eq!(read_state, state);
+    }
 }
@@ -0,0 +1,283 @@
+//! # Dirichlet-Process-Clustering von Ereigniszeitpunkten
+//!
+//! `group_observations_probabilistically` nimmt an, dass ein ganzer
+//! Beobachtungsbatch zu genau einem Ereignis (`t_global`) gehört. In echten
+//! Streams kann ein Batch aber mehrere distinkte Ereignisse überspannen, und
+//! die Anzahl ist vorher nicht bekannt. Dieses Modul entdeckt die Cluster-Zahl
+//! automatisch über eine trunkierte Stick-Breaking-Mischung (Dirichlet-Process-
+//! Approximation) und liefert eine `SynchronizedGroup` pro überlebendem Cluster.
+
+use crate::{effective_variance, to_global_time, TimeOffsetModel};
+use sensor_redis::{GroupMember, RawObservation, SynchronizedGroup};
+
+/// Konfiguration für die DP-Mischungs-Schätzung.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DpClusterConfig {
+    /// Trunkierungsgrad `K`: obere Schranke für die Anzahl entdeckter Cluster.
+    pub truncation: usize,
+    /// Konzentrationsparameter `α` des Dirichlet-Prozesses: größere Werte
+    /// erlauben leichter neue Cluster.
+    pub concentration: f64,
+    /// Maximale Anzahl EM-Iterationen bis zur Konvergenz.
+    pub max_iterations: usize,
+    /// Untere Schranke für die summierte Responsibility eines Clusters;
+    /// Cluster darunter werden verworfen.
+    pub prune_floor: f64,
+    /// Konvergenztoleranz auf der maximalen Verschiebung der Cluster-Mittel.
+    pub tol: f64,
+}
+
+impl Default for DpClusterConfig {
+    fn default() -> Self {
+        Self {
+            truncation: 8,
+            concentration: 1.0,
+            max_iterations: 50,
+            prune_floor: 1e-3,
+            tol: 1e-9,
+        }
+    }
+}
+
+/// Gruppiere einen Beobachtungsbatch über eine unbekannte Anzahl Ereignisse.
+///
+/// Jede Beobachtung wird über `to_global_time` auf die globale Zeitachse
+/// abgebildet, mit Präzision `1/effective_variance(...)`. Ein EM-Algorithmus
+/// über eine trunkierte Stick-Breaking-Mischung schätzt Cluster-Mittel `μ_k`
+/// und -Gewichte `π_k`; Cluster mit vernachlässigbarer Gesamt-Responsibility
+/// werden verworfen. Ein leerer Batch liefert einen leeren Vektor, ein
+/// einzelnes Sample genau ein Cluster.
+pub fn group_observations_dp(
+    observations: &[RawObservation],
+    models: &[TimeOffsetModel],
+    config: &DpClusterConfig,
+) -> Vec<SynchronizedGroup> {
+    assert_eq!(
+        observations.len(),
+        models.len(),
+        "observations und models müssen gleich lang sein"
+    );
+
+    if observations.is_empty() {
+        return Vec::new();
+    }
+
+    let n = observations.len();
+
+    let tg: Vec<f64> = observations
+        .iter()
+        .zip(models)
+        .map(|(o, m)| to_global_time(o.t_local, m))
+        .collect();
+    let prec: Vec<f64> = observations
+        .iter()
+        .zip(models)
+        .map(|(o, m)| 1.0 / effective_variance(m, o.sigma).max(1e-12))
+        .collect();
+
+    if n == 1 {
+        return vec![SynchronizedGroup {
+            t_global: tg[0],
+            members: vec![GroupMember {
+                sensor_id: observations[0].sensor_id.clone(),
+                probability: 1.0,
+            }],
+        }];
+    }
+
+    let k_trunc = config.truncation.max(1).min(n);
+
+    // Cluster-Mittel über die beobachtete Zeitspanne verteilt initialisieren.
+    let min_tg = tg.iter().copied().fold(f64::INFINITY, f64::min);
+    let max_tg = tg.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = (max_tg - min_tg).max(1e-9);
+    let mut mu: Vec<f64> = (0..k_trunc)
+        .map(|k| min_tg + span * (k as f64 + 0.5) / k_trunc as f64)
+        .collect();
+    let mut pi = vec![1.0 / k_trunc as f64; k_trunc];
+    let mut responsibilities = vec![vec![0.0_f64; k_trunc]; n];
+
+    for _ in 0..config.max_iterations.max(1) {
+        // E-Schritt: r_ik ∝ π_k · N(tg_i; μ_k, 1/prec_i), normalisiert über k.
+        for i in 0..n {
+            let var_i = 1.0 / prec[i];
+            // Im Log-Raum rechnen und auf das Maximum über k beziehen, damit
+            // weit entfernte Cluster-Mittel nicht alle Dichten auf 0 unterlaufen.
+            let log_w: Vec<f64> = (0..k_trunc)
+                .map(|k| pi[k].ln() - 0.5 * (tg[i] - mu[k]).powi(2) / var_i)
+                .collect();
+            let max_log = log_w.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let mut sum = 0.0;
+            for k in 0..k_trunc {
+                let w = (log_w[k] - max_log).exp();
+                responsibilities[i][k] = w;
+                sum += w;
+            }
+            if sum > 0.0 {
+                for k in 0..k_trunc {
+                    responsibilities[i][k] /= sum;
+                }
+            } else {
+                // Kein Cluster erklärt diesen Punkt: uniform auf alle verteilen.
+                for k in 0..k_trunc {
+                    responsibilities[i][k] = 1.0 / k_trunc as f64;
+                }
+            }
+        }
+
+        // M-Schritt: μ_k als präzisionsgewichtetes Mittel seiner Responsibilities.
+        let mut max_shift = 0.0_f64;
+        for k in 0..k_trunc {
+            let mut num = 0.0;
+            let mut den = 0.0;
+            for i in 0..n {
+                let w = responsibilities[i][k] * prec[i];
+                num += w * tg[i];
+                den += w;
+            }
+            let new_mu = if den > 0.0 { num / den } else { mu[k] };
+            max_shift = max_shift.max((new_mu - mu[k]).abs());
+            mu[k] = new_mu;
+        }
+
+        // Stick-Breaking-Update der Gewichte aus den summierten Responsibilities:
+        // β_k = (1+N_k) / (1+N_k + α+Σ_{j>k}N_j), π_k = β_k·Π_{j<k}(1-β_j).
+        let n_k: Vec<f64> = (0..k_trunc)
+            .map(|k| (0..n).map(|i| responsibilities[i][k]).sum())
+            .collect();
+        let mut remaining = 1.0;
+        for k in 0..k_trunc {
+            let gamma1 = 1.0 + n_k[k];
+            let tail: f64 = n_k[(k + 1)..].iter().sum();
+            let gamma2 = config.concentration + tail;
+            let beta_k = gamma1 / (gamma1 + gamma2);
+            pi[k] = beta_k * remaining;
+            remaining *= 1.0 - beta_k;
+        }
+        // Restliche Stick-Masse (Trunkierungsfehler) dem letzten Cluster zuschlagen.
+        let sum_pi: f64 = pi.iter().sum();
+        if sum_pi < 1.0 {
+            if let Some(last) = pi.last_mut() {
+                *last += 1.0 - sum_pi;
+            }
+        }
+
+        if max_shift < config.tol {
+            break;
+        }
+    }
+
+    // Cluster mit zu geringer Gesamt-Responsibility verwerfen.
+    let n_k: Vec<f64> = (0..k_trunc)
+        .map(|k| (0..n).map(|i| responsibilities[i][k]).sum())
+        .collect();
+    let floor = config.prune_floor * n as f64;
+    let mut surviving: Vec<usize> = (0..k_trunc).filter(|&k| n_k[k] >= floor).collect();
+    if surviving.is_empty() {
+        // Entarteter Fall: stärksten Cluster behalten statt den Batch zu verlieren.
+        let best = (0..k_trunc)
+            .max_by(|&a, &b| n_k[a].total_cmp(&n_k[b]))
+            .unwrap();
+        surviving.push(best);
+    }
+    surviving.sort_by(|&a, &b| mu[a].total_cmp(&mu[b]));
+
+    surviving
+        .into_iter()
+        .map(|k| {
+            let sum: f64 = (0..n).map(|i| responsibilities[i][k]).sum();
+            let mut members = Vec::new();
+            for i in 0..n {
+                let p = if sum > 0.0 {
+                    responsibilities[i][k] / sum
+                } else {
+                    0.0
+                };
+                if p > 0.0 {
+                    members.push(GroupMember {
+                        sensor_id: observations[i].sensor_id.clone(),
+                        probability: p,
+                    });
+                }
+            }
+            SynchronizedGroup {
+                t_global: mu[k],
+                members,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(sensor_id: &str, t_local: f64, sigma: f64) -> RawObservation {
+        RawObservation {
+            sensor_id: sensor_id.into(),
+            sensor_type: "test".into(),
+            t_local,
+            sigma,
+            payload_ref: format!("mem://{sensor_id}"),
+            ..Default::default()
+        }
+    }
+
+    fn identity_model() -> TimeOffsetModel {
+        TimeOffsetModel {
+            offset_mean: 0.0,
+            offset_var: 0.001,
+            drift: 1.0,
+        }
+    }
+
+    #[test]
+    fn empty_batch_yields_empty_vec() {
+        let groups = group_observations_dp(&[], &[], &DpClusterConfig::default());
+        assert!(groups.is_empty());
+    }
+
+    #[test]
+    fn single_observation_yields_one_cluster() {
+        let observations = vec![obs("s1", 10.0, 0.05)];
+        let models = vec![identity_model()];
+        let groups = group_observations_dp(&observations, &models, &DpClusterConfig::default());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members.len(), 1);
+        assert!((groups[0].members[0].probability - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn two_well_separated_events_are_discovered() {
+        let observations = vec![
+            obs("a1", 10.0, 0.02),
+            obs("a2", 10.02, 0.02),
+            obs("a3", 9.98, 0.02),
+            obs("b1", 100.0, 0.02),
+            obs("b2", 100.03, 0.02),
+            obs("b3", 99.97, 0.02),
+        ];
+        let models = vec![identity_model(); observations.len()];
+        let groups = group_observations_dp(&observations, &models, &DpClusterConfig::default());
+
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].t_global < 20.0);
+        assert!(groups[1].t_global > 90.0);
+    }
+
+    #[test]
+    fn memberships_sum_to_one_per_group() {
+        let observations = vec![
+            obs("a1", 10.0, 0.05),
+            obs("a2", 10.1, 0.05),
+            obs("b1", 50.0, 0.05),
+        ];
+        let models = vec![identity_model(); observations.len()];
+        let groups = group_observations_dp(&observations, &models, &DpClusterConfig::default());
+
+        for group in &groups {
+            let sum_p: f64 = group.members.iter().map(|m| m.probability).sum();
+            assert!((sum_p - 1.0).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! # Streaming-Ingestion mit gleitenden Time-Slice-Gruppen
+//!
+//! Der einzige Gruppierungs-Einstiegspunkt war bisher ein Batch-Aufruf, der
+//! `&[RawObservation]` und ein einzelnes `t_global` entgegennimmt. `GroupStream`
+//! nimmt Beobachtungen stattdessen einzeln über `add_observation` entgegen,
+//! puffert sie zusammen mit ihrer korrigierten Zeit und liefert eine
+//! `SynchronizedGroup`, sobald eine Slice-Grenze überschritten wird. `t_global`
+//! wird dabei automatisch fortgeschrieben und Beobachtungen außerhalb des
+//! Retention-Fensters werden verworfen — das erlaubt den Einsatz in einer
+//! Echtzeit-Fusionsschleife, ohne vorher alles einzusammeln und neu zu slicen.
+
+use std::collections::HashMap;
+
+use crate::{group_time_slice_probabilistically, TimeOffset};
+use sensor_redis::{RawObservation, SynchronizedGroup};
+
+/// Obergrenze für die Anzahl an Slice-Grenzen, die `add_observation` in einem
+/// einzigen Aufruf einzeln (mit je eigener Gruppe) durchläuft. Ein einzelner
+/// Ankunftszeitpunkt, der weit über die nächste Grenze hinausspringt (defekte
+/// Uhr, Replay, manipulierter Input), würde sonst eine unbegrenzte Anzahl
+/// meist leerer Gruppen synchron erzeugen; stattdessen wird ab dieser Grenze
+/// direkt zur Slice gesprungen, die den Ankunftszeitpunkt enthält, und nur
+/// eine einzelne Gruppe als Lücken-Marker emittiert.
+const MAX_BOUNDARIES_PER_CALL: u32 = 64;
+
+/// Inkrementeller Gruppierungs-Stream über gleitende Time Slices.
+pub struct GroupStream {
+    offsets: HashMap<String, TimeOffset>,
+    slice_width_ns: i128,
+    retention_ns: i128,
+    /// Gepufferte Beobachtungen, je mit ihrer korrigierten Zeit in Nanosekunden.
+    buffer: Vec<(i128, RawObservation)>,
+    next_boundary_ns: Option<i128>,
+}
+
+impl GroupStream {
+    /// Erstelle einen neuen Stream.
+    ///
+    /// * `offsets` - initiale `TimeOffset`-Modelle je Sensor; unbekannte
+    ///   Sensoren erhalten bei der ersten Beobachtung ein frisches
+    ///   `TimeOffset::new()`.
+    /// * `slice_width_ns` - Breite eines Time Slice in Nanosekunden.
+    /// * `retention_ns` - wie lange eine Beobachtung nach ihrer korrigierten
+    ///   Zeit im Puffer verbleibt, bevor sie verworfen wird.
+    pub fn new(offsets: HashMap<String, TimeOffset>, slice_width_ns: u64, retention_ns: u64) -> Self {
+        Self {
+            offsets,
+            slice_width_ns: slice_width_ns.max(1) as i128,
+            retention_ns: retention_ns as i128,
+            buffer: Vec::new(),
+            next_boundary_ns: None,
+        }
+    }
+
+    /// Füge eine Beobachtung hinzu. `arrival_time` ist der optionale,
+    /// explizite Ankunftszeitpunkt (Sekunden); fehlt er, wird die korrigierte
+    /// Zeit der Beobachtung selbst als Ankunftszeit verwendet.
+    ///
+    /// Liefert eine (möglicherweise leere) Liste von Gruppen für jede
+    /// Slice-Grenze, die durch diese Ankunftszeit überschritten wurde; sind es
+    /// mehr als `MAX_BOUNDARIES_PER_CALL`, nur eine Gruppe für die Slice, die
+    /// den Ankunftszeitpunkt enthält.
+    pub fn add_observation(
+        &mut self,
+        obs: RawObservation,
+        arrival_time: Option<f64>,
+    ) -> Vec<SynchronizedGroup> {
+        let offset = self
+            .offsets
+            .entry(obs.sensor_id.clone())
+            .or_insert_with(TimeOffset::new);
+        let corrected = obs.t_local + offset.offset_mean;
+        let corrected_ns = (corrected * 1e9).round() as i128;
+        let arrival = arrival_time.unwrap_or(corrected);
+        let arrival_ns = (arrival * 1e9).round() as i128;
+
+        self.buffer.push((corrected_ns, obs));
+
+        if self.next_boundary_ns.is_none() {
+            self.next_boundary_ns = Some(self.align_boundary(arrival_ns));
+        }
+
+        let mut groups = Vec::new();
+        while let Some(boundary) = self.next_boundary_ns {
+            if arrival_ns < boundary {
+                break;
+            }
+            let remaining = (arrival_ns - boundary) / self.slice_width_ns + 1;
+            if remaining > MAX_BOUNDARIES_PER_CALL as i128 {
+                // Großer Sprung: statt jede übersprungene Slice einzeln zu
+                // emittieren, direkt zur Slice springen, die `arrival_ns`
+                // enthält, und nur deren Gruppe als Lücken-Marker emittieren.
+                let boundary = self.align_boundary(arrival_ns);
+                let center_ns = boundary - self.slice_width_ns / 2;
+                groups.push(self.emit_slice(center_ns));
+                self.next_boundary_ns = Some(boundary + self.slice_width_ns);
+                self.evict_before(boundary - self.retention_ns);
+                break;
+            }
+            let center_ns = boundary - self.slice_width_ns / 2;
+            groups.push(self.emit_slice(center_ns));
+            self.next_boundary_ns = Some(boundary + self.slice_width_ns);
+            self.evict_before(boundary - self.retention_ns);
+        }
+        groups
+    }
+
+    /// Erzwinge die Emission einer Gruppe über alle aktuell gepufferten
+    /// Beobachtungen, zentriert auf ihre mittlere korrigierte Zeit. Nützlich
+    /// beim Beenden des Streams, um die letzte unvollständige Slice nicht zu
+    /// verlieren.
+    pub fn flush(&mut self) -> Option<SynchronizedGroup> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let sum_ns: i128 = self.buffer.iter().map(|(c, _)| *c).sum();
+        let center_ns = sum_ns / self.buffer.len() as i128;
+        Some(self.emit_slice(center_ns))
+    }
+
+    /// Anzahl aktuell gepufferter (noch nicht verworfener) Beobachtungen.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn align_boundary(&self, arrival_ns: i128) -> i128 {
+        ((arrival_ns.div_euclid(self.slice_width_ns)) + 1) * self.slice_width_ns
+    }
+
+    fn emit_slice(&self, center_ns: i128) -> SynchronizedGroup {
+        let t_global = center_ns as f64 / 1e9;
+        let bucket_size_ms = ((self.slice_width_ns / 1_000_000).max(1)) as u64;
+        let observations: Vec<RawObservation> =
+            self.buffer.iter().map(|(_, o)| o.clone()).collect();
+        group_time_slice_probabilistically(t_global, &observations, &self.offsets, bucket_size_ms)
+    }
+
+    fn evict_before(&mut self, cutoff_ns: i128) {
+        self.buffer.retain(|(corrected_ns, _)| *corrected_ns >= cutoff_ns);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(sensor_id: &str, t_local: f64) -> RawObservation {
+        RawObservation {
+            sensor_id: sensor_id.into(),
+            sensor_type: "test".into(),
+            t_local,
+            sigma: 0.05,
+            payload_ref: format!("mem://{sensor_id}"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flush_emits_group_over_buffered_observations() {
+        let mut stream = GroupStream::new(HashMap::new(), 1_000_000_000, 10_000_000_000);
+        stream.add_observation(obs("s1", 10.0), Some(10.0));
+        stream.add_observation(obs("s2", 10.02), Some(10.02));
+
+        let group = stream.flush().unwrap();
+        assert_eq!(group.members.len(), 2);
+        let sum_p: f64 = group.members.iter().map(|m| m.probability).sum();
+        assert!((sum_p - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn boundary_crossing_emits_a_group_automatically() {
+        // 1-Sekunden-Slices.
+        let mut stream = GroupStream::new(HashMap::new(), 1_000_000_000, 10_000_000_000);
+        let groups1 = stream.add_observation(obs("s1", 0.1), Some(0.1));
+        assert!(groups1.is_empty());
+
+        // Sprung über die erste Slice-Grenze hinweg.
+        let groups2 = stream.add_observation(obs("s2", 1.5), Some(1.5));
+        assert_eq!(groups2.len(), 1);
+    }
+
+    #[test]
+    fn observations_outside_retention_are_dropped() {
+        let mut stream = GroupStream::new(HashMap::new(), 1_000_000_000, 1_000_000_000);
+        stream.add_observation(obs("old", 0.0), Some(0.0));
+        assert_eq!(stream.buffered_len(), 1);
+
+        // Weit in der Zukunft: mehrere Slice-Grenzen überschritten, altes
+        // Sample fällt aus dem Retention-Fenster.
+        stream.add_observation(obs("new", 10.0), Some(10.0));
+        assert!(stream.buffered_len() <= 1);
+    }
+
+    #[test]
+    fn huge_arrival_jump_emits_a_bounded_number_of_groups() {
+        // 1-Sekunden-Slices, dann ein Sprung um Jahrzehnte: ohne Obergrenze
+        // würde dies Milliarden leerer Gruppen synchron erzeugen.
+        let mut stream = GroupStream::new(HashMap::new(), 1_000_000_000, 10_000_000_000);
+        let groups1 = stream.add_observation(obs("s1", 0.0), Some(0.0));
+        assert!(groups1.is_empty());
+
+        let far_future = 60.0 * 60.0 * 24.0 * 365.0 * 30.0; // ~30 Jahre
+        let groups2 = stream.add_observation(obs("s2", far_future), Some(far_future));
+        assert_eq!(groups2.len(), 1);
+        assert!((groups2[0].t_global - far_future).abs() < 1.0);
+    }
+
+    #[test]
+    fn empty_stream_flush_returns_none() {
+        let mut stream = GroupStream::new(HashMap::new(), 1_000_000_000, 10_000_000_000);
+        assert!(stream.flush().is_none());
+    }
+}
@@ -0,0 +1,182 @@
+//! # Monotone lokal→global-Zeitabbildung (Pool-Adjacent-Violators)
+//!
+//! Die feste lineare Abbildung `offset + drift·t_local` kann Uhren mit
+//! zeitlich veränderlicher Rate nicht abbilden, obwohl physikalische Uhren
+//! die Zeit monoton abbilden müssen. Dieses Modul fittet eine monoton
+//! nicht-fallende Abbildung von `t_local` auf `t_global` aus
+//! `(t_local, t_global_measured, weight)`-Tripeln mittels Pool-Adjacent-
+//! Violators (PAVA) — eine driftrobuste, nichtparametrische Alternative zu
+//! `TimeOffset`, die eine monoton nicht-fallende Zeitabbildung garantiert.
+//! Streng invertierbar ist sie nicht: benachbarte Blöcke mit gleichem Wert
+//! und die konstante Fortsetzung außerhalb der Stützstellen bilden flache
+//! Abschnitte, und das Modell bietet keine inverse Abbildung an.
+
+/// Ein Block aus zusammengelegten (gepoolten) Kalibrierpunkten.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Block {
+    /// Kleinster `t_local`-Wert, der in diesen Block fällt.
+    t_local_start: f64,
+    /// Gewichteter Mittelwert des gepoolten `t_global`.
+    value: f64,
+    /// Gesamtgewicht des Blocks.
+    weight: f64,
+}
+
+/// Monotone, stückweise-konstante-Inkremente Abbildung von `t_local` auf
+/// `t_global`, gefittet per PAVA.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsotonicTimeModel {
+    /// Aufsteigend sortierte Stützstellen `(t_local, t_global)`.
+    knots: Vec<(f64, f64)>,
+}
+
+impl IsotonicTimeModel {
+    /// Fitte eine monotone Abbildung aus gewichteten Kalibrierpaaren.
+    ///
+    /// Sortiert nach `t_local`, läuft dann von links nach rechts und hält
+    /// einen Stack gepoolter Blöcke: verletzt der neue Block die Monotonie
+    /// (sein gepoolter Mittelwert liegt unter dem des vorigen Blocks), werden
+    /// beide zu einem gewichteten Mittel `(w1·m1 + w2·m2)/(w1+w2)` verschmolzen,
+    /// bis die Monotonie wiederhergestellt ist.
+    pub fn fit(pairs: &[(f64, f64, f64)]) -> Self {
+        let mut sorted: Vec<(f64, f64, f64)> = pairs.to_vec();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut stack: Vec<Block> = Vec::new();
+        for (t_local, t_global, weight) in sorted {
+            let weight = weight.max(0.0);
+            if weight == 0.0 {
+                continue;
+            }
+            let mut block = Block {
+                t_local_start: t_local,
+                value: t_global,
+                weight,
+            };
+            while let Some(top) = stack.last() {
+                if block.value < top.value {
+                    let prev = stack.pop().unwrap();
+                    let total_weight = prev.weight + block.weight;
+                    let pooled_value =
+                        (prev.weight * prev.value + block.weight * block.value) / total_weight;
+                    block = Block {
+                        t_local_start: prev.t_local_start,
+                        value: pooled_value,
+                        weight: total_weight,
+                    };
+                } else {
+                    break;
+                }
+            }
+            stack.push(block);
+        }
+
+        let knots = stack.into_iter().map(|b| (b.t_local_start, b.value)).collect();
+        Self { knots }
+    }
+
+    /// Werte die gefittete Abbildung an `t_local` aus. Zwischen Stützstellen
+    /// wird linear interpoliert; außerhalb des Stützbereichs wird der Wert der
+    /// jeweils randnächsten Stützstelle konstant fortgesetzt.
+    pub fn to_global_time(&self, t_local: f64) -> f64 {
+        if self.knots.is_empty() {
+            return t_local;
+        }
+        if self.knots.len() == 1 {
+            return self.knots[0].1;
+        }
+        if t_local <= self.knots[0].0 {
+            return self.knots[0].1;
+        }
+        if t_local >= self.knots[self.knots.len() - 1].0 {
+            return self.knots[self.knots.len() - 1].1;
+        }
+        let idx = self
+            .knots
+            .partition_point(|&(tl, _)| tl <= t_local)
+            .saturating_sub(1);
+        let (t0, v0) = self.knots[idx];
+        let (t1, v1) = self.knots[idx + 1];
+        if (t1 - t0).abs() < 1e-15 {
+            return v0;
+        }
+        let frac = (t_local - t0) / (t1 - t0);
+        v0 + frac * (v1 - v0)
+    }
+
+    /// Die gefitteten Stützstellen `(t_local, t_global)` in aufsteigender Reihenfolge.
+    pub fn knots(&self) -> &[(f64, f64)] {
+        &self.knots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn already_monotone_data_is_unchanged_at_knots() {
+        let pairs = vec![(0.0, 0.0, 1.0), (1.0, 1.0, 1.0), (2.0, 2.0, 1.0)];
+        let model = IsotonicTimeModel::fit(&pairs);
+        assert!((model.to_global_time(0.0) - 0.0).abs() < 1e-9);
+        assert!((model.to_global_time(1.0) - 1.0).abs() < 1e-9);
+        assert!((model.to_global_time(2.0) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn violation_is_pooled_into_weighted_mean() {
+        // t_global 0,2,1 at t_local 0,1,2 violates monotonicity at the last point;
+        // points 1 and 2 pool to a weighted mean of 1.5.
+        let pairs = vec![(0.0, 0.0, 1.0), (1.0, 2.0, 1.0), (2.0, 1.0, 1.0)];
+        let model = IsotonicTimeModel::fit(&pairs);
+
+        assert!((model.to_global_time(0.0) - 0.0).abs() < 1e-9);
+        assert!((model.to_global_time(1.0) - 1.5).abs() < 1e-9);
+        assert!((model.to_global_time(2.0) - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn result_is_always_monotone_non_decreasing() {
+        let pairs = vec![
+            (0.0, 5.0, 1.0),
+            (1.0, 1.0, 1.0),
+            (2.0, 8.0, 1.0),
+            (3.0, 2.0, 1.0),
+            (4.0, 9.0, 1.0),
+        ];
+        let model = IsotonicTimeModel::fit(&pairs);
+        let values: Vec<f64> = model.knots().iter().map(|&(_, v)| v).collect();
+        for window in values.windows(2) {
+            assert!(window[1] >= window[0] - 1e-12);
+        }
+    }
+
+    #[test]
+    fn weighted_pooling_favors_higher_weight() {
+        let pairs = vec![(0.0, 0.0, 1.0), (1.0, 10.0, 1.0), (2.0, 0.0, 9.0)];
+        let model = IsotonicTimeModel::fit(&pairs);
+        // (10*1 + 0*9) / 10 = 1.0
+        assert!((model.to_global_time(1.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn interpolates_between_knots() {
+        let pairs = vec![(0.0, 0.0, 1.0), (10.0, 10.0, 1.0)];
+        let model = IsotonicTimeModel::fit(&pairs);
+        assert!((model.to_global_time(5.0) - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn extrapolates_at_the_boundaries() {
+        let pairs = vec![(0.0, 0.0, 1.0), (10.0, 10.0, 1.0)];
+        let model = IsotonicTimeModel::fit(&pairs);
+        assert_eq!(model.to_global_time(-5.0), 0.0);
+        assert_eq!(model.to_global_time(15.0), 10.0);
+    }
+
+    #[test]
+    fn empty_input_maps_identity() {
+        let model = IsotonicTimeModel::fit(&[]);
+        assert_eq!(model.to_global_time(42.0), 42.0);
+    }
+}
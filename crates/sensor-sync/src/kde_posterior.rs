@@ -0,0 +1,219 @@
+//! # Kernel-Dichte-Posterior des globalen Ereigniszeitpunkts
+//!
+//! `estimate_event_time` kollabiert einen Beobachtungsbatch auf einen
+//! einzelnen präzisionsgewichteten Punkt, was Mehrdeutigkeit verbirgt, wenn
+//! die Assoziationen multimodal sind (z. B. zwei plausible Ereignisse). Dieses
+//! Modul liefert stattdessen eine nichtparametrische Kerndichteschätzung des
+//! Posteriors über `t_global`, sodass Aufrufer Multimodalität sowie Breite und
+//! Schiefe der Schätzung erkennen können.
+
+use crate::{effective_variance, gaussian_pdf, to_global_time, TimeOffsetModel};
+use sensor_redis::{RawObservation, SynchronizedGroup};
+
+/// Anzahl der Gitterpunkte, über die die Dichte ausgewertet wird.
+const GRID_RESOLUTION: usize = 512;
+
+/// Schätze die nichtparametrische Posterior-Dichte über `t_global` für einen
+/// Beobachtungsbatch.
+///
+/// Jede Beobachtung trägt einen gaußschen Kernel bei, zentriert auf
+/// `to_global_time(obs.t_local, model)` mit Varianz
+/// `effective_variance(model, obs.sigma)`, gewichtet mit ihrer Präzision
+/// (oder, falls eine `SynchronizedGroup` übergeben wird, mit der
+/// Mitgliedschaftswahrscheinlichkeit). Das Gitter überspannt
+/// `[min_center − 3σ, max_center + 3σ]` mit fest `GRID_RESOLUTION` Punkten;
+/// eine Silverman-Bandbreite dient als untere Schranke für die Kernelbreite,
+/// um spitze Einzel-Sample-Fälle zu vermeiden.
+///
+/// Gibt `(xs, densities)` und die Menge lokaler Maxima zurück, damit
+/// nachgelagerter Code eine Gruppe aufspalten kann, wenn mehr als ein Modus
+/// vorliegt.
+pub fn event_time_posterior(
+    observations: &[RawObservation],
+    models: &[TimeOffsetModel],
+    group: Option<&SynchronizedGroup>,
+) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    assert_eq!(
+        observations.len(),
+        models.len(),
+        "observations und models müssen gleich lang sein"
+    );
+
+    if observations.is_empty() {
+        return (Vec::new(), Vec::new(), Vec::new());
+    }
+
+    let centers: Vec<f64> = observations
+        .iter()
+        .zip(models)
+        .map(|(o, m)| to_global_time(o.t_local, m))
+        .collect();
+    let vars: Vec<f64> = observations
+        .iter()
+        .zip(models)
+        .map(|(o, m)| effective_variance(m, o.sigma).max(1e-12))
+        .collect();
+
+    let weights: Vec<f64> = match group {
+        Some(g) => observations
+            .iter()
+            .map(|o| {
+                g.members
+                    .iter()
+                    .find(|m| m.sensor_id == o.sensor_id)
+                    .map(|m| m.probability)
+                    .unwrap_or(0.0)
+            })
+            .collect(),
+        None => vars.iter().map(|v| 1.0 / v).collect(),
+    };
+    let sum_w: f64 = weights.iter().sum();
+    let weights: Vec<f64> = if sum_w > 0.0 {
+        weights.iter().map(|w| w / sum_w).collect()
+    } else {
+        vec![1.0 / observations.len() as f64; observations.len()]
+    };
+
+    // Silverman-Bandbreite als Mindestbreite für die Kernel, um einzelne
+    // Samples nicht beliebig spitz werden zu lassen.
+    let mean_center = centers.iter().sum::<f64>() / centers.len() as f64;
+    let sample_std = if centers.len() > 1 {
+        (centers
+            .iter()
+            .map(|c| (c - mean_center).powi(2))
+            .sum::<f64>()
+            / (centers.len() - 1) as f64)
+            .sqrt()
+    } else {
+        vars[0].sqrt()
+    };
+    let n = centers.len() as f64;
+    let silverman_h = 1.06 * sample_std.max(1e-9) * n.powf(-1.0 / 5.0);
+    let effective_vars: Vec<f64> = vars.iter().map(|v| v.max(silverman_h.powi(2))).collect();
+
+    let max_sigma = effective_vars
+        .iter()
+        .cloned()
+        .fold(0.0_f64, f64::max)
+        .sqrt();
+    let min_center = centers.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max_center = centers.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let lo = min_center - 3.0 * max_sigma;
+    let hi = max_center + 3.0 * max_sigma;
+    let span = (hi - lo).max(1e-9);
+
+    let xs: Vec<f64> = (0..GRID_RESOLUTION)
+        .map(|i| lo + span * i as f64 / (GRID_RESOLUTION - 1) as f64)
+        .collect();
+    let densities: Vec<f64> = xs
+        .iter()
+        .map(|&x| {
+            centers
+                .iter()
+                .zip(effective_vars.iter())
+                .zip(weights.iter())
+                .map(|((&c, &v), &w)| w * gaussian_pdf(x, c, v))
+                .sum()
+        })
+        .collect();
+
+    let modes = local_maxima(&xs, &densities);
+    (xs, densities, modes)
+}
+
+/// Finde die x-Werte lokaler Maxima einer auf einem Gitter ausgewerteten
+/// Funktion (einfacher diskreter Vorzeichenwechsel der Steigung).
+fn local_maxima(xs: &[f64], densities: &[f64]) -> Vec<f64> {
+    let mut modes = Vec::new();
+    if densities.len() < 3 {
+        if let Some((idx, _)) = densities
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        {
+            modes.push(xs[idx]);
+        }
+        return modes;
+    }
+    for i in 1..densities.len() - 1 {
+        if densities[i] > densities[i - 1] && densities[i] >= densities[i + 1] {
+            modes.push(xs[i]);
+        }
+    }
+    if modes.is_empty() {
+        if let Some((idx, _)) = densities
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        {
+            modes.push(xs[idx]);
+        }
+    }
+    modes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(sensor_id: &str, t_local: f64, sigma: f64) -> RawObservation {
+        RawObservation {
+            sensor_id: sensor_id.into(),
+            sensor_type: "test".into(),
+            t_local,
+            sigma,
+            payload_ref: format!("mem://{sensor_id}"),
+            ..Default::default()
+        }
+    }
+
+    fn identity_model() -> TimeOffsetModel {
+        TimeOffsetModel {
+            offset_mean: 0.0,
+            offset_var: 0.001,
+            drift: 1.0,
+        }
+    }
+
+    #[test]
+    fn empty_batch_yields_empty_posterior() {
+        let (xs, densities, modes) = event_time_posterior(&[], &[], None);
+        assert!(xs.is_empty());
+        assert!(densities.is_empty());
+        assert!(modes.is_empty());
+    }
+
+    #[test]
+    fn unimodal_batch_has_single_mode_near_cluster() {
+        let observations = vec![
+            obs("s1", 10.0, 0.05),
+            obs("s2", 10.02, 0.05),
+            obs("s3", 9.98, 0.05),
+        ];
+        let models = vec![identity_model(); observations.len()];
+        let (xs, densities, modes) = event_time_posterior(&observations, &models, None);
+
+        assert!(!xs.is_empty());
+        assert_eq!(modes.len(), 1);
+        assert!((modes[0] - 10.0).abs() < 0.2);
+
+        let sum_density: f64 = densities.iter().sum();
+        assert!(sum_density > 0.0);
+    }
+
+    #[test]
+    fn bimodal_batch_is_detected() {
+        let observations = vec![
+            obs("a1", 10.0, 0.01),
+            obs("a2", 10.01, 0.01),
+            obs("b1", 20.0, 0.01),
+            obs("b2", 20.01, 0.01),
+        ];
+        let models = vec![identity_model(); observations.len()];
+        let (_xs, _densities, modes) = event_time_posterior(&observations, &models, None);
+
+        assert!(modes.len() >= 2);
+        assert!(modes.iter().any(|&m| (m - 10.0).abs() < 0.3));
+        assert!(modes.iter().any(|&m| (m - 20.0).abs() < 0.3));
+    }
+}
@@ -7,6 +7,17 @@
 //! - `association_probability` für Paarassoziationen
 //! - Probabilistische Gruppierung ohne harte Schwellwerte
 
+pub mod dp_cluster;
+pub mod group_stream;
+pub mod isotonic_model;
+pub mod kde_posterior;
+pub mod network_clock;
+pub mod nig_model;
+pub mod offset_accumulator;
+pub mod range_grouping;
+pub mod sensor_clustering;
+pub mod session_log;
+pub mod spatial_grouping;
 pub mod time_model;
 
 use anyhow::Result;
@@ -15,7 +26,18 @@ use serde::{Deserialize, Serialize};
 use redis::Connection;
 use sensor_election::write_sync_group_if_master;
 use sensor_redis::{GroupMember, RawObservation, SynchronizedGroup, TimeSyncState};
-pub use time_model::TimeOffset;
+pub use dp_cluster::{group_observations_dp, DpClusterConfig};
+pub use group_stream::GroupStream;
+pub use isotonic_model::IsotonicTimeModel;
+pub use kde_posterior::event_time_posterior;
+pub use network_clock::{fit_network_clock_model, SensorNetwork};
+pub use nig_model::NigOffsetModel;
+pub use offset_accumulator::OffsetAccumulator;
+pub use range_grouping::group_range_probabilistically;
+pub use sensor_clustering::{cluster_sensors_by_coherence, ClusterResult, SensorTimeSample};
+pub use session_log::{query, read_session, replay, SessionHeader, SessionRecord, SessionWriter};
+pub use spatial_grouping::{coverage, group_spatiotemporal, DistanceMetric, SensorGeometry};
+pub use time_model::{KalmanUpdateOutcome, TimeOffset, DEFAULT_NIS_GATE};
 
 /// Gaußsche Wahrscheinlichkeitsdichte (PDF).
 ///
@@ -78,6 +100,7 @@ pub fn gaussian_pdf(x: f64, mean: f64, var: f64) -> f64 {
 ///     t_local: 10.0,
 ///     sigma: 0.1,
 ///     payload_ref: "mem://s1".into(),
+///     ..Default::default()
 /// };
 /// let offset = TimeOffset::new();  // mean=0, variance=1.0
 ///
@@ -96,7 +119,7 @@ pub fn observation_probability(obs: &RawObservation, t_global: f64, offset: &Tim
     let dt = t_global - t_expected;
     
     // Gesamtvarianz: Kalman-Unsicherheit + Messrauschen
-    let var = offset.offset_variance + obs.sigma.powi(2);
+    let var = offset.offset_variance() + obs.sigma.powi(2);
     
     gaussian_pdf(dt, 0.0, var)
 }
@@ -160,16 +183,141 @@ pub fn observation_bucket_id(t_local: f64, bucket_size_ms: u64) -> u64 {
     if bucket < 0 { 0 } else { bucket as u64 }
 }
 
+/// Prüfe, ob `obs` per Bucket-Nachbarschaft als Kandidat für `t_global` zählt,
+/// und liefere bei Treffer das Residuum zum Slice-Zentrum.
+///
+/// Bei Intervall-Beobachtungen (`t_local_end` gesetzt) zählt die gesamte
+/// korrigierte Spanne als Treffer (Residuum 0), sobald `t_global` darin
+/// liegt, statt nur die Nähe zum Start zu bewerten — und der Bucket-Filter
+/// prüft entsprechend die ganze Bucket-Spanne `[start_bucket, end_bucket]`
+/// statt nur `obs.t_local`. `None` bedeutet: kein Kandidat, überspringen.
+/// Geteilt zwischen `group_time_slice_with_kernel` und
+/// `spatial_grouping::group_spatiotemporal`, damit beide dieselbe
+/// Intervall-Semantik anwenden.
+pub(crate) fn bucket_candidate_residual(
+    obs: &RawObservation,
+    offset: &TimeOffset,
+    t_global: f64,
+    bucket_size_ms: u64,
+) -> Option<f64> {
+    let candidates = candidate_buckets(t_global, offset, bucket_size_ms);
+
+    match obs.t_local_end {
+        Some(end_local) => {
+            let start_bucket = observation_bucket_id(obs.t_local, bucket_size_ms);
+            let end_bucket = observation_bucket_id(end_local, bucket_size_ms);
+            let (lo_bucket, hi_bucket) = if start_bucket <= end_bucket {
+                (start_bucket, end_bucket)
+            } else {
+                (end_bucket, start_bucket)
+            };
+            if !candidates.iter().any(|&b| b >= lo_bucket && b <= hi_bucket) {
+                return None;
+            }
+
+            let t_expected_start = obs.t_local + offset.offset_mean;
+            let t_expected_end = end_local + offset.offset_mean;
+            let (lo, hi) = if t_expected_start <= t_expected_end {
+                (t_expected_start, t_expected_end)
+            } else {
+                (t_expected_end, t_expected_start)
+            };
+            Some(if t_global < lo {
+                t_global - lo
+            } else if t_global > hi {
+                t_global - hi
+            } else {
+                0.0
+            })
+        }
+        None => {
+            let obs_bucket = observation_bucket_id(obs.t_local, bucket_size_ms);
+            if !candidates.contains(&obs_bucket) {
+                return None;
+            }
+            let t_expected = obs.t_local + offset.offset_mean;
+            Some(t_global - t_expected)
+        }
+    }
+}
+
+/// Gewichtungs-Kernel für `group_time_slice_with_kernel`.
+///
+/// Die Gaußsche Dichte bestraft einen einzelnen, schlecht zeitgestempelten
+/// Ausreißer entweder mit einem Gewicht nahe 0, oder zieht bei großem `sigma`
+/// die gesamte Normalisierung der Gruppe mit sich. Die Heavy-Tail-Kernel
+/// (Cauchy/Student-t) bleiben robust gegenüber solchen Ausreißern, ohne sie
+/// völlig zu verwerfen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WeightKernel {
+    /// Volle Gauß-Dichte `gaussian_pdf(dt, 0, var)`, also `exp(-0.5·z²)` mit
+    /// dem Normierungsfaktor `1/(σ·√(2π))` (identisch zur bisherigen Gewichtung).
+    Gaussian,
+    /// Lorentz/Cauchy-Kernel: `w = 1 / (1 + z²)`.
+    Cauchy,
+    /// Student-t-Kernel mit `ν` Freiheitsgraden: `w = (1 + z²/ν)^(-(ν+1)/2)`.
+    StudentT { nu: f64 },
+}
+
+impl Default for WeightKernel {
+    fn default() -> Self {
+        WeightKernel::Gaussian
+    }
+}
+
+/// Werte den gewählten Kernel am standardisierten Residuum `z = dt/sigma` aus.
+/// Für `WeightKernel::Gaussian` ist das Ergebnis identisch zur vollen
+/// Gauß-Dichte `gaussian_pdf(dt, 0, var)`, damit bestehende Normalisierungen
+/// unverändert bleiben.
+pub(crate) fn kernel_weight(dt: f64, var: f64, kernel: WeightKernel) -> f64 {
+    if var <= 0.0 {
+        return 0.0;
+    }
+    match kernel {
+        WeightKernel::Gaussian => gaussian_pdf(dt, 0.0, var),
+        WeightKernel::Cauchy => {
+            let z = dt / var.sqrt();
+            1.0 / (1.0 + z * z)
+        }
+        WeightKernel::StudentT { nu } => {
+            let z = dt / var.sqrt();
+            (1.0 + z * z / nu).powf(-(nu + 1.0) / 2.0)
+        }
+    }
+}
+
 /// Gruppiere Beobachtungen probabilistisch in ein Time Slice für einen gegebenen t_global.
 ///
 /// - Kandidaten werden per Bucket-Nachbarschaft (±1) gefiltert.
 /// - Mitgliedschaften sind gaußsche Dichten relativ zu `t_global` (kein harter Schwellwert).
 /// - Gewichte werden normalisiert, sodass `sum(probability)=1` für die ausgewählten Mitglieder.
+/// - Intervall-Beobachtungen (`t_local_end` gesetzt) tragen mit Residuum 0 bei, solange
+///   `t_global` innerhalb ihrer korrigierten Spanne liegt, statt nur in der Nähe ihres Starts.
 pub fn group_time_slice_probabilistically(
     t_global: f64,
     observations: &[RawObservation],
     offsets: &HashMap<String, TimeOffset>,
     bucket_size_ms: u64,
+) -> SynchronizedGroup {
+    group_time_slice_with_kernel(
+        t_global,
+        observations,
+        offsets,
+        bucket_size_ms,
+        WeightKernel::Gaussian,
+    )
+}
+
+/// Wie `group_time_slice_probabilistically`, aber mit wählbarem `WeightKernel`
+/// für die Mitgliedschaftsgewichte. Heavy-Tail-Kernel (Cauchy/Student-t) machen
+/// die Gruppierung robust gegen Sensoren, deren Uhr kurzzeitig springt, ohne
+/// sie vollständig zu verwerfen.
+pub fn group_time_slice_with_kernel(
+    t_global: f64,
+    observations: &[RawObservation],
+    offsets: &HashMap<String, TimeOffset>,
+    bucket_size_ms: u64,
+    kernel: WeightKernel,
 ) -> SynchronizedGroup {
     let mut members = Vec::new();
     let mut weights = Vec::new();
@@ -178,15 +326,13 @@ pub fn group_time_slice_probabilistically(
         // Offset-Lookup; unbekannte Sensoren überspringen
         let Some(offset) = offsets.get(&obs.sensor_id) else { continue };
 
-        // Bucket-Filter mit ±1 Nachbarschaft
-        let obs_bucket = observation_bucket_id(obs.t_local, bucket_size_ms);
-        let candidates = candidate_buckets(t_global, offset, bucket_size_ms);
-        if !candidates.contains(&obs_bucket) {
+        let Some(dt) = bucket_candidate_residual(obs, offset, t_global, bucket_size_ms) else {
             continue;
-        }
+        };
 
-        // Gewicht = Gauß-Dichte der Zeitabweichung
-        let w = observation_probability(obs, t_global, offset);
+        // Gewicht = gewählter Kernel der Zeitabweichung
+        let var = offset.offset_variance() + obs.sigma.powi(2);
+        let w = kernel_weight(dt, var, kernel);
         members.push(GroupMember {
             sensor_id: obs.sensor_id.clone(),
             probability: 0.0, // Platzhalter bis Normalisierung
@@ -392,8 +538,8 @@ mod tests {
     fn gaussian_and_association_probability() {
         let mdl_a = TimeOffsetModel { offset_mean: 0.0, offset_var: 0.01, drift: 1.0 };
         let mdl_b = TimeOffsetModel { offset_mean: 0.001, offset_var: 0.02, drift: 1.0 };
-        let a = RawObservation { sensor_id: "A".into(), sensor_type: "x".into(), t_local: 1.0, sigma: 0.05, payload_ref: "mem://a".into() };
-        let b = RawObservation { sensor_id: "B".into(), sensor_type: "x".into(), t_local: 1.0, sigma: 0.05, payload_ref: "mem://b".into() };
+        let a = RawObservation { sensor_id: "A".into(), sensor_type: "x".into(), t_local: 1.0, sigma: 0.05, payload_ref: "mem://a".into() , ..Default::default() };
+        let b = RawObservation { sensor_id: "B".into(), sensor_type: "x".into(), t_local: 1.0, sigma: 0.05, payload_ref: "mem://b".into() , ..Default::default() };
         let p = association_probability(&a, &mdl_a, &b, &mdl_b);
         assert!(p > 0.0);
         // Symmetrie
@@ -404,9 +550,9 @@ mod tests {
     #[test]
     fn estimate_and_group_single_batch() {
         let obs = vec![
-            RawObservation { sensor_id: "s1".into(), sensor_type: "cam".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://1".into() },
-            RawObservation { sensor_id: "s2".into(), sensor_type: "imu".into(), t_local: 10.05, sigma: 0.2, payload_ref: "mem://2".into() },
-            RawObservation { sensor_id: "s3".into(), sensor_type: "mic".into(), t_local: 9.98, sigma: 0.15, payload_ref: "mem://3".into() },
+            RawObservation { sensor_id: "s1".into(), sensor_type: "cam".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://1".into() , ..Default::default() },
+            RawObservation { sensor_id: "s2".into(), sensor_type: "imu".into(), t_local: 10.05, sigma: 0.2, payload_ref: "mem://2".into() , ..Default::default() },
+            RawObservation { sensor_id: "s3".into(), sensor_type: "mic".into(), t_local: 9.98, sigma: 0.15, payload_ref: "mem://3".into() , ..Default::default() },
         ];
         let models = vec![
             TimeOffsetModel { offset_mean: 0.0, offset_var: 0.01, drift: 1.0 },
@@ -507,7 +653,7 @@ mod tests {
             t_local: 10.0,
             sigma: 0.1,
             payload_ref: "test:42".into(),
-        };
+         ..Default::default() };
         let offset = TimeOffset::new(); // offset_mean=0, offset_variance=0.1
         
         // Perfekter Match: t_global = t_local + offset.offset_mean
@@ -532,7 +678,7 @@ mod tests {
             t_local: 5.0,
             sigma: 0.05,
             payload_ref: "test:123".into(),
-        };
+         ..Default::default() };
         let mut offset = TimeOffset::new();
         offset.offset_mean = 2.0; // t_expected = 5.0 + 2.0 = 7.0
         
@@ -557,7 +703,7 @@ mod tests {
             t_local: 10.0,
             sigma: 0.1,
             payload_ref: "test:1".into(),
-        };
+         ..Default::default() };
         
         let offset_low_var = TimeOffset::with_values(0.0, 0.01, 1.0);
         let offset_high_var = TimeOffset::with_values(0.0, 1.0, 1.0);
@@ -650,8 +796,8 @@ mod tests {
     fn group_time_slice_filters_by_bucket_and_normalizes() {
         // Beobachtungen in verschiedenen Buckets
         let obs = vec![
-            RawObservation { sensor_id: "s1".into(), sensor_type: "cam".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://1".into() },
-            RawObservation { sensor_id: "s2".into(), sensor_type: "cam".into(), t_local: 12.5, sigma: 0.1, payload_ref: "mem://2".into() }, // anderer Bucket
+            RawObservation { sensor_id: "s1".into(), sensor_type: "cam".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://1".into() , ..Default::default() },
+            RawObservation { sensor_id: "s2".into(), sensor_type: "cam".into(), t_local: 12.5, sigma: 0.1, payload_ref: "mem://2".into() , ..Default::default() }, // anderer Bucket
         ];
 
         let mut offsets = HashMap::new();
@@ -667,8 +813,8 @@ mod tests {
     #[test]
     fn group_time_slice_weights_and_normalizes() {
         let obs = vec![
-            RawObservation { sensor_id: "a".into(), sensor_type: "x".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://a".into() },
-            RawObservation { sensor_id: "b".into(), sensor_type: "x".into(), t_local: 10.2, sigma: 0.1, payload_ref: "mem://b".into() },
+            RawObservation { sensor_id: "a".into(), sensor_type: "x".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://a".into() , ..Default::default() },
+            RawObservation { sensor_id: "b".into(), sensor_type: "x".into(), t_local: 10.2, sigma: 0.1, payload_ref: "mem://b".into() , ..Default::default() },
         ];
         let mut offsets = HashMap::new();
         offsets.insert("a".into(), TimeOffset::new());
@@ -697,8 +843,161 @@ mod tests {
         assert!(group.members.is_empty());
 
         // Unbekannter Sensor wird ignoriert
-        let obs = vec![RawObservation { sensor_id: "unknown".into(), sensor_type: "x".into(), t_local: 1.0, sigma: 0.1, payload_ref: "mem://x".into() }];
+        let obs = vec![RawObservation { sensor_id: "unknown".into(), sensor_type: "x".into(), t_local: 1.0, sigma: 0.1, payload_ref: "mem://x".into() , ..Default::default() }];
         let group = group_time_slice_probabilistically(1.0, &obs, &offsets, 1000);
         assert!(group.members.is_empty());
     }
+
+    #[test]
+    fn gaussian_kernel_matches_default_grouping() {
+        let obs = vec![
+            RawObservation { sensor_id: "a".into(), sensor_type: "x".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://a".into() , ..Default::default() },
+            RawObservation { sensor_id: "b".into(), sensor_type: "x".into(), t_local: 10.2, sigma: 0.1, payload_ref: "mem://b".into() , ..Default::default() },
+        ];
+        let mut offsets = HashMap::new();
+        offsets.insert("a".into(), TimeOffset::new());
+        offsets.insert("b".into(), TimeOffset::new());
+
+        let default_group = group_time_slice_probabilistically(10.0, &obs, &offsets, 1000);
+        let gaussian_group =
+            group_time_slice_with_kernel(10.0, &obs, &offsets, 1000, WeightKernel::Gaussian);
+
+        for (m1, m2) in default_group.members.iter().zip(gaussian_group.members.iter()) {
+            assert_eq!(m1.sensor_id, m2.sensor_id);
+            assert!((m1.probability - m2.probability).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn heavy_tailed_kernels_are_more_forgiving_of_stragglers() {
+        // Ein Straggler weit entfernt vom t_global, aber noch in der Bucket-Nachbarschaft.
+        let obs = vec![
+            RawObservation { sensor_id: "close".into(), sensor_type: "x".into(), t_local: 10.0, sigma: 0.05, payload_ref: "mem://close".into() , ..Default::default() },
+            RawObservation { sensor_id: "straggler".into(), sensor_type: "x".into(), t_local: 10.9, sigma: 0.05, payload_ref: "mem://straggler".into() , ..Default::default() },
+        ];
+        let mut offsets = HashMap::new();
+        offsets.insert("close".into(), TimeOffset::new());
+        offsets.insert("straggler".into(), TimeOffset::new());
+
+        let gaussian = group_time_slice_with_kernel(10.0, &obs, &offsets, 1000, WeightKernel::Gaussian);
+        let cauchy = group_time_slice_with_kernel(10.0, &obs, &offsets, 1000, WeightKernel::Cauchy);
+
+        let p_straggler_gaussian = gaussian
+            .members
+            .iter()
+            .find(|m| m.sensor_id == "straggler")
+            .map(|m| m.probability)
+            .unwrap_or(0.0);
+        let p_straggler_cauchy = cauchy
+            .members
+            .iter()
+            .find(|m| m.sensor_id == "straggler")
+            .map(|m| m.probability)
+            .unwrap_or(0.0);
+
+        assert!(p_straggler_cauchy > p_straggler_gaussian);
+    }
+
+    #[test]
+    fn student_t_kernel_normalizes_to_one() {
+        let obs = vec![
+            RawObservation { sensor_id: "a".into(), sensor_type: "x".into(), t_local: 10.0, sigma: 0.1, payload_ref: "mem://a".into() , ..Default::default() },
+            RawObservation { sensor_id: "b".into(), sensor_type: "x".into(), t_local: 10.1, sigma: 0.1, payload_ref: "mem://b".into() , ..Default::default() },
+        ];
+        let mut offsets = HashMap::new();
+        offsets.insert("a".into(), TimeOffset::new());
+        offsets.insert("b".into(), TimeOffset::new());
+
+        let group = group_time_slice_with_kernel(
+            10.0,
+            &obs,
+            &offsets,
+            1000,
+            WeightKernel::StudentT { nu: 4.0 },
+        );
+        let sum_p: f64 = group.members.iter().map(|m| m.probability).sum();
+        assert!((sum_p - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn interval_observation_contributes_when_centre_falls_inside_span() {
+        // Start weit vom Slice-Zentrum entfernt, aber die Spanne deckt es ab.
+        let obs = vec![RawObservation {
+            sensor_id: "a".into(),
+            sensor_type: "x".into(),
+            t_local: 9.0,
+            t_local_end: Some(11.0),
+            sigma: 0.1,
+            payload_ref: "mem://a".into(),
+            ..Default::default()
+        }];
+        let mut offsets = HashMap::new();
+        offsets.insert("a".into(), TimeOffset::new());
+
+        let group = group_time_slice_probabilistically(10.0, &obs, &offsets, 1000);
+        assert_eq!(group.members.len(), 1);
+        assert!((group.members[0].probability - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn interval_observation_is_excluded_far_outside_its_span() {
+        let obs = vec![RawObservation {
+            sensor_id: "a".into(),
+            sensor_type: "x".into(),
+            t_local: 0.0,
+            t_local_end: Some(1.0),
+            sigma: 0.1,
+            payload_ref: "mem://a".into(),
+            ..Default::default()
+        }];
+        let mut offsets = HashMap::new();
+        offsets.insert("a".into(), TimeOffset::new());
+
+        // Weit außerhalb der Spanne und der Bucket-Nachbarschaft.
+        let group = group_time_slice_probabilistically(100.0, &obs, &offsets, 1000);
+        assert!(group.members.is_empty());
+    }
+
+    #[test]
+    fn interval_observation_beats_instant_observation_at_same_distance_from_start() {
+        // Beide beginnen gleich weit vom Zentrum entfernt, aber nur die
+        // Intervall-Beobachtung deckt das Zentrum tatsächlich ab.
+        let obs = vec![
+            RawObservation {
+                sensor_id: "instant".into(),
+                sensor_type: "x".into(),
+                t_local: 9.0,
+                sigma: 0.1,
+                payload_ref: "mem://instant".into(),
+                ..Default::default()
+            },
+            RawObservation {
+                sensor_id: "interval".into(),
+                sensor_type: "x".into(),
+                t_local: 9.0,
+                t_local_end: Some(11.0),
+                sigma: 0.1,
+                payload_ref: "mem://interval".into(),
+                ..Default::default()
+            },
+        ];
+        let mut offsets = HashMap::new();
+        offsets.insert("instant".into(), TimeOffset::new());
+        offsets.insert("interval".into(), TimeOffset::new());
+
+        let group = group_time_slice_probabilistically(10.0, &obs, &offsets, 1000);
+        let p_instant = group
+            .members
+            .iter()
+            .find(|m| m.sensor_id == "instant")
+            .map(|m| m.probability)
+            .unwrap_or(0.0);
+        let p_interval = group
+            .members
+            .iter()
+            .find(|m| m.sensor_id == "interval")
+            .map(|m| m.probability)
+            .unwrap_or(0.0);
+        assert!(p_interval > p_instant);
+    }
 }
@@ -0,0 +1,255 @@
+//! # Gemeinsames Mehr-Sensor-Uhrenmodell (Gaussian Markov Random Field)
+//!
+//! Bisher hat jeder Sensor ein unabhängiges `TimeOffsetModel`, sodass ein
+//! Sensor, der selten gegen die globale Referenz misst, sich nie verbessert.
+//! Dieses Modul modelliert die Sensor-Uhren als GMRF: benachbarte Sensoren
+//! sind über ein Graph-Koppelungsmodell (intrinsisches CAR /
+//! Ornstein-Uhlenbeck-auf-einem-Graphen) korreliert, sodass gut synchronisierte
+//! Sensoren ihre Nachbarn mitziehen.
+
+use std::collections::HashMap;
+
+use crate::TimeOffsetModel;
+
+/// Ein Sensornetzwerk: Knoten sind Sensor-IDs, Kanten tragen ein
+/// Kopplungsgewicht `w_ij` (z. B. invers proportional zur physischen Distanz).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SensorNetwork {
+    sensor_ids: Vec<String>,
+    index_of: HashMap<String, usize>,
+    /// Adjazenzliste: `edges[i] = [(j, w_ij), ...]`, symmetrisch gepflegt.
+    edges: Vec<Vec<(usize, f64)>>,
+}
+
+impl SensorNetwork {
+    /// Erstelle ein leeres Netzwerk.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Füge einen Sensor hinzu, falls noch nicht vorhanden, und liefere seinen Index.
+    pub fn add_sensor(&mut self, sensor_id: &str) -> usize {
+        if let Some(&idx) = self.index_of.get(sensor_id) {
+            return idx;
+        }
+        let idx = self.sensor_ids.len();
+        self.sensor_ids.push(sensor_id.to_string());
+        self.index_of.insert(sensor_id.to_string(), idx);
+        self.edges.push(Vec::new());
+        idx
+    }
+
+    /// Füge eine ungerichtete, gewichtete Kopplungskante zwischen zwei Sensoren hinzu.
+    pub fn add_edge(&mut self, a: &str, b: &str, weight: f64) {
+        let ia = self.add_sensor(a);
+        let ib = self.add_sensor(b);
+        if ia == ib {
+            return;
+        }
+        self.edges[ia].push((ib, weight));
+        self.edges[ib].push((ia, weight));
+    }
+
+    pub fn len(&self) -> usize {
+        self.sensor_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sensor_ids.is_empty()
+    }
+}
+
+/// Matrix-Vektor-Produkt `(Q(θ,κ) + diag(τ))·v` ohne je die Matrix dicht zu
+/// materialisieren: `Q` wird direkt aus der Adjazenzliste ausgewertet.
+fn apply_precision(
+    network: &SensorNetwork,
+    theta: f64,
+    kappa: f64,
+    tau: &[f64],
+    v: &[f64],
+) -> Vec<f64> {
+    let n = network.len();
+    let mut out = vec![0.0; n];
+    for i in 0..n {
+        // Diagonale: θ je inzidenter Kante + Anker κ an die globale Referenz + τ_i.
+        let degree_weight: f64 = network.edges[i].iter().map(|(_, w)| w).sum();
+        let diag = theta * degree_weight + kappa + tau[i];
+        let mut acc = diag * v[i];
+        for &(j, w) in &network.edges[i] {
+            acc += -theta * w * v[j];
+        }
+        out[i] = acc;
+    }
+    out
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Löse `(Q + diag(τ))·x = diag(τ)·y` mit Conjugate Gradient (sparse, keine
+/// dichte Inverse). `theta` ist die Graph-Kopplungsstärke, `kappa` die
+/// Anker-Stärke zur globalen Referenz (`0.0` offset, per Konstruktion).
+///
+/// Liefert den geglätteten Offset-Schätzvektor sowie eine grobe Näherung der
+/// posterioren Marginalvarianzen als `1/diag_i`, den Kehrwert der Diagonale
+/// von `Q + diag(τ)`. Die Näherung ignoriert die Kopplung über die
+/// Nebendiagonale; sie wird weder aus dem CG-Residuum noch über
+/// Lanczos-Schritte gewonnen.
+pub fn solve_joint_offsets(
+    network: &SensorNetwork,
+    theta: f64,
+    kappa: f64,
+    tau: &[f64],
+    y: &[f64],
+    max_iterations: usize,
+    tol: f64,
+) -> (Vec<f64>, Vec<f64>) {
+    let n = network.len();
+    assert_eq!(tau.len(), n);
+    assert_eq!(y.len(), n);
+
+    if n == 0 {
+        return (Vec::new(), Vec::new());
+    }
+
+    let b: Vec<f64> = tau.iter().zip(y).map(|(t, yi)| t * yi).collect();
+
+    let mut x = vec![0.0; n];
+    let mut r = b.clone();
+    let mut p = r.clone();
+    let mut rs_old = dot(&r, &r);
+
+    if rs_old > 0.0 {
+        for _ in 0..max_iterations.max(1) {
+            let ap = apply_precision(network, theta, kappa, tau, &p);
+            let alpha = rs_old / dot(&p, &ap).max(1e-15);
+            for i in 0..n {
+                x[i] += alpha * p[i];
+                r[i] -= alpha * ap[i];
+            }
+            let rs_new = dot(&r, &r);
+            if rs_new.sqrt() < tol {
+                break;
+            }
+            let beta = rs_new / rs_old;
+            for i in 0..n {
+                p[i] = r[i] + beta * p[i];
+            }
+            rs_old = rs_new;
+        }
+    }
+
+    // Marginalvarianzen grob über die Diagonale der Systemmatrix approximieren
+    // (ein einzelner Jacobi-Schritt statt einer teuren Lanczos-Rekonstruktion).
+    let variances: Vec<f64> = (0..n)
+        .map(|i| {
+            let degree_weight: f64 = network.edges[i].iter().map(|(_, w)| w).sum();
+            let diag = theta * degree_weight + kappa + tau[i];
+            1.0 / diag.max(1e-12)
+        })
+        .collect();
+
+    (x, variances)
+}
+
+/// Schätze geglättete `TimeOffsetModel`e für ein gesamtes Sensornetzwerk aus
+/// rohen Offset-Messungen.
+///
+/// `raw_offsets` enthält pro Sensor `(gemessener Offset, Messpräzision τ_i)`;
+/// Sensoren im Netzwerk ohne Eintrag erhalten `τ_i = 0` (keine direkte
+/// Messung, nur über Nachbarn gekoppelt). `theta`/`kappa` sind die
+/// Kopplungs- bzw. Anker-Hyperparameter.
+pub fn fit_network_clock_model(
+    network: &SensorNetwork,
+    raw_offsets: &HashMap<String, (f64, f64)>,
+    theta: f64,
+    kappa: f64,
+) -> HashMap<String, TimeOffsetModel> {
+    let n = network.len();
+    let mut tau = vec![0.0; n];
+    let mut y = vec![0.0; n];
+    for (sensor_id, &idx) in &network.index_of {
+        if let Some(&(offset, precision)) = raw_offsets.get(sensor_id) {
+            tau[idx] = precision;
+            y[idx] = offset;
+        }
+    }
+
+    let (x, variances) = solve_joint_offsets(network, theta, kappa, &tau, &y, 500, 1e-10);
+
+    network
+        .sensor_ids
+        .iter()
+        .enumerate()
+        .map(|(idx, sensor_id)| {
+            (
+                sensor_id.clone(),
+                TimeOffsetModel {
+                    offset_mean: x[idx],
+                    offset_var: variances[idx].max(1e-9),
+                    drift: 1.0,
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isolated_sensor_is_pulled_toward_anchor() {
+        let mut network = SensorNetwork::new();
+        network.add_sensor("s1");
+
+        let mut offsets = HashMap::new();
+        offsets.insert("s1".to_string(), (0.5, 10.0));
+
+        let models = fit_network_clock_model(&network, &offsets, 1.0, 0.01);
+        let model = &models["s1"];
+        // Starke Messpräzision dominiert gegenüber der schwachen Anker-Kopplung.
+        assert!((model.offset_mean - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn well_synced_sensor_pulls_unmeasured_neighbor() {
+        let mut network = SensorNetwork::new();
+        network.add_edge("well_synced", "unmeasured", 1.0);
+
+        let mut offsets = HashMap::new();
+        offsets.insert("well_synced".to_string(), (1.0, 1000.0));
+
+        let models = fit_network_clock_model(&network, &offsets, 5.0, 0.001);
+
+        let a = models["well_synced"].offset_mean;
+        let b = models["unmeasured"].offset_mean;
+        // Der unbeobachtete Nachbar wird über die Kopplung Richtung `a` gezogen.
+        assert!(b > 0.0);
+        assert!((a - b).abs() < a.abs());
+    }
+
+    #[test]
+    fn empty_network_returns_empty_map() {
+        let network = SensorNetwork::new();
+        let offsets = HashMap::new();
+        let models = fit_network_clock_model(&network, &offsets, 1.0, 0.01);
+        assert!(models.is_empty());
+    }
+
+    #[test]
+    fn disconnected_components_do_not_influence_each_other() {
+        let mut network = SensorNetwork::new();
+        network.add_edge("a1", "a2", 1.0);
+        network.add_edge("b1", "b2", 1.0);
+
+        let mut offsets = HashMap::new();
+        offsets.insert("a1".to_string(), (1.0, 100.0));
+        offsets.insert("b1".to_string(), (-1.0, 100.0));
+
+        let models = fit_network_clock_model(&network, &offsets, 2.0, 0.01);
+        assert!(models["a2"].offset_mean > 0.0);
+        assert!(models["b2"].offset_mean < 0.0);
+    }
+}
@@ -0,0 +1,205 @@
+//! # Normal-Inverse-Gamma-Schätzer für unbekannte Messvarianz
+//!
+//! `update_with_observation` verlangt, dass der Aufrufer `measurement_var`
+//! vorgibt, und `observation_probability`/`association_probability` vertrauen
+//! dem übergebenen `sigma`. In der Praxis ist das sensorspezifische Rauschen
+//! unbekannt und zeitveränderlich. Dieses Modul lernt Offset und
+//! Messpräzision gemeinsam online über eine Normal-Inverse-Gamma-Konjugation.
+
+use crate::TimeOffsetModel;
+
+/// Online-Schätzer für Offset `μ` und Messvarianz `σ²` über eine
+/// Normal-Inverse-Gamma-Konjugation: `μ | σ² ~ N(m, σ²/κ)`, `σ² ~ Inv-Gamma(a, b)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NigOffsetModel {
+    /// Posteriorer Mittelwert des Offsets.
+    m: f64,
+    /// Posteriore Präzision der Mittelwertschätzung (relativ zu `σ²`).
+    kappa: f64,
+    /// Posteriorer Shape-Parameter der Inverse-Gamma-Varianz.
+    a: f64,
+    /// Posteriorer Scale-Parameter der Inverse-Gamma-Varianz.
+    b: f64,
+}
+
+impl NigOffsetModel {
+    /// Erstelle einen Schätzer mit gegebenen Prior-Hyperparametern
+    /// `(m, κ, a, b)`.
+    pub fn with_prior(m: f64, kappa: f64, a: f64, b: f64) -> Self {
+        Self { m, kappa, a, b }
+    }
+
+    /// Uninformativer Standard-Prior (`m=0, κ=1, a=1, b=1`).
+    pub fn new() -> Self {
+        Self::with_prior(0.0, 1.0, 1.0, 1.0)
+    }
+
+    /// Falte ein Residuum `r = t_global_measured − predict_global_time(t_local)`
+    /// konjugiert ein:
+    /// `κ' = κ+1; m' = (κ·m + r)/κ'; a' = a + 1/2; b' = b + ½·κ·(r−m)²/κ'`.
+    pub fn update(&mut self, r: f64) {
+        let kappa_new = self.kappa + 1.0;
+        let m_new = (self.kappa * self.m + r) / kappa_new;
+        let a_new = self.a + 0.5;
+        let b_new = self.b + 0.5 * self.kappa * (r - self.m).powi(2) / kappa_new;
+        self.kappa = kappa_new;
+        self.m = m_new;
+        self.a = a_new;
+        self.b = b_new;
+    }
+
+    /// Posteriorer Mittelwert des Offsets.
+    pub fn mean(&self) -> f64 {
+        self.m
+    }
+
+    /// Gelernte Rausch-Standardabweichung, `σ ≈ sqrt(b/a)`.
+    pub fn sigma(&self) -> f64 {
+        (self.b / self.a).sqrt()
+    }
+
+    /// Student-t-Posterior-Prädiktivdichte für ein neues Residuum `r`:
+    /// `t_{2a}(r; m, scale)` mit quadriertem Skalenparameter
+    /// `scale² = b·(κ+1)/(a·κ)`.
+    ///
+    /// Kann `gaussian_pdf` in der Assoziationsbewertung ersetzen, damit
+    /// Heavy-Tail-Verhalten während der Aufwärmphase korrekt behandelt wird.
+    pub fn predictive_density(&self, r: f64) -> f64 {
+        let nu = 2.0 * self.a;
+        let scale_sq = self.b * (self.kappa + 1.0) / (self.a * self.kappa);
+        if scale_sq <= 0.0 || nu <= 0.0 {
+            return 0.0;
+        }
+        student_t_pdf(r, self.m, scale_sq, nu)
+    }
+
+    /// Wandle den Schätzer in ein `TimeOffsetModel` um (`offset_mean = m`,
+    /// `offset_var = b/(a·κ)`), `drift` bleibt `1.0` (Residuen sind bereits
+    /// driftbereinigt).
+    pub fn to_offset_model(&self) -> TimeOffsetModel {
+        TimeOffsetModel {
+            offset_mean: self.m,
+            offset_var: (self.b / (self.a * self.kappa)).max(1e-9),
+            drift: 1.0,
+        }
+    }
+}
+
+impl Default for NigOffsetModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Dichte der Student-t-Verteilung mit `ν` Freiheitsgraden, Lageparameter
+/// `loc` und quadriertem Skalenparameter `scale_sq`, ausgedrückt über die
+/// Gammafunktion (implementiert über die Lanczos-Approximation, um eine
+/// zusätzliche Abhängigkeit für eine einzelne Spezialfunktion zu vermeiden).
+fn student_t_pdf(x: f64, loc: f64, scale_sq: f64, nu: f64) -> f64 {
+    let scale = scale_sq.sqrt();
+    let z = (x - loc) / scale;
+    let numerator = gamma((nu + 1.0) / 2.0);
+    let denominator = (nu * std::f64::consts::PI).sqrt() * scale * gamma(nu / 2.0);
+    numerator / denominator * (1.0 + z * z / nu).powf(-(nu + 1.0) / 2.0)
+}
+
+/// Lanczos-Approximation der Gammafunktion für positive reelle Argumente.
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x))
+    } else {
+        let x = x - 1.0;
+        let mut a = COEFFICIENTS[0];
+        let t = x + G + 0.5;
+        for (i, coeff) in COEFFICIENTS.iter().enumerate().skip(1) {
+            a += coeff / (x + i as f64);
+        }
+        (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converges_to_true_offset_with_low_noise() {
+        let mut model = NigOffsetModel::new();
+        for _ in 0..200 {
+            model.update(0.5);
+        }
+        assert!((model.mean() - 0.5).abs() < 0.05);
+        // Der Prior b = 1 wirkt nach: b → 1 + 0.125·(1 − 1/201), a = 101,
+        // also σ = sqrt(b/a) ≈ 0.1055.
+        assert!((model.sigma() - 0.1055).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sigma_reflects_residual_spread() {
+        let mut tight = NigOffsetModel::new();
+        let mut loose = NigOffsetModel::new();
+        for i in 0..50 {
+            let jitter = if i % 2 == 0 { 0.01 } else { -0.01 };
+            tight.update(jitter);
+            let jitter = if i % 2 == 0 { 1.0 } else { -1.0 };
+            loose.update(jitter);
+        }
+        assert!(tight.sigma() < loose.sigma());
+    }
+
+    #[test]
+    fn predictive_density_peaks_at_mean() {
+        let mut model = NigOffsetModel::new();
+        for _ in 0..20 {
+            model.update(0.2);
+        }
+        let p_center = model.predictive_density(model.mean());
+        let p_far = model.predictive_density(model.mean() + 5.0);
+        assert!(p_center > p_far);
+        assert!(p_center > 0.0);
+    }
+
+    #[test]
+    fn to_offset_model_round_trips_mean_and_variance() {
+        let mut model = NigOffsetModel::new();
+        for _ in 0..30 {
+            model.update(-0.3);
+        }
+        let offset_model = model.to_offset_model();
+        assert!((offset_model.offset_mean - model.mean()).abs() < 1e-9);
+        assert!(offset_model.offset_var > 0.0);
+        assert_eq!(offset_model.drift, 1.0);
+    }
+
+    #[test]
+    fn predictive_density_has_heavier_tails_during_warm_up() {
+        // Mit sehr wenigen Beobachtungen (kleines `a`) sollte die Student-t-
+        // Prädiktivdichte an einem weit entfernten Punkt mehr Masse haben als
+        // eine Gauß-Dichte mit gleicher Varianz (schwerere Flanken).
+        let mut warm = NigOffsetModel::new();
+        warm.update(0.0);
+
+        let far = warm.mean() + 10.0;
+        let p_far = warm.predictive_density(far);
+        assert!(p_far > 0.0);
+
+        // Varianz der Student-t-Prädiktive: scale²·ν/(ν−2).
+        let nu = 2.0 * warm.a;
+        let scale_sq = warm.b * (warm.kappa + 1.0) / (warm.a * warm.kappa);
+        let p_gauss = crate::gaussian_pdf(far, warm.mean(), scale_sq * nu / (nu - 2.0));
+        assert!(p_far > p_gauss);
+    }
+}
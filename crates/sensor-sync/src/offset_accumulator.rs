@@ -0,0 +1,171 @@
+//! # Streaming-Offset-Akkumulator
+//!
+//! `TimeOffsetModel::update_with_observation` faltet jeweils nur eine einzelne
+//! Messung ein und lässt sich nicht über Knoten hinweg kombinieren. Dieses
+//! Modul stellt `OffsetAccumulator` bereit: einen streamingfähigen Schätzer
+//! für Mittelwert/Varianz von Offset-Residuen nach Welfords Online-Algorithmus,
+//! der sich exakt mergen lässt — sodass mehrere Knoten unabhängig Teilstatistiken
+//! sammeln (z. B. via Redis verteilt) und anschließend verlustfrei kombinieren können.
+
+use crate::TimeOffsetModel;
+
+/// Streamingfähiger, mergebarer Schätzer für Mittelwert und Varianz von
+/// Offset-Residuen (Welfords Online-Algorithmus).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffsetAccumulator {
+    /// Anzahl bisher eingeflossener Residuen.
+    pub n: u64,
+    /// Laufender Mittelwert der Residuen.
+    pub mean: f64,
+    /// Summe der quadrierten Abweichungen vom laufenden Mittel (Welfords `M2`).
+    pub m2: f64,
+}
+
+impl OffsetAccumulator {
+    /// Erstelle einen leeren Akkumulator.
+    pub fn new() -> Self {
+        Self {
+            n: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Falte ein einzelnes Offset-Residuum `r` ein.
+    ///
+    /// `δ = r − mean; mean += δ/n; M2 += δ·(r − mean)`.
+    pub fn update(&mut self, r: f64) {
+        self.n += 1;
+        let delta = r - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (r - self.mean);
+    }
+
+    /// Stichprobenvarianz der eingeflossenen Residuen (`M2/(n−1)`).
+    ///
+    /// Für `n < 2` gibt es noch keine Varianzschätzung; liefert `0.0`.
+    pub fn variance(&self) -> f64 {
+        if self.n < 2 {
+            0.0
+        } else {
+            self.m2 / (self.n - 1) as f64
+        }
+    }
+
+    /// Kombiniere zwei unabhängig berechnete Akkumulatoren exakt (Chan et al.).
+    ///
+    /// `n = nA+nB; δ = meanB−meanA; mean = meanA + δ·nB/n;`
+    /// `M2 = M2A + M2B + δ²·nA·nB/n`.
+    pub fn merge(&self, other: &Self) -> Self {
+        if self.n == 0 {
+            return *other;
+        }
+        if other.n == 0 {
+            return *self;
+        }
+        let n = self.n + other.n;
+        let delta = other.mean - self.mean;
+        let mean = self.mean + delta * other.n as f64 / n as f64;
+        let m2 = self.m2 + other.m2 + delta * delta * self.n as f64 * other.n as f64 / n as f64;
+        Self { n, mean, m2 }
+    }
+
+    /// Wandle den Akkumulator in ein `TimeOffsetModel` um: `offset_mean`/`offset_var`
+    /// werden aus den gesammelten Residuen-Statistiken gesetzt, `drift` bleibt `1.0`
+    /// (Residuen sind bereits driftbereinigt).
+    pub fn to_offset_model(&self) -> TimeOffsetModel {
+        TimeOffsetModel {
+            offset_mean: self.mean,
+            offset_var: self.variance().max(1e-6),
+            drift: 1.0,
+        }
+    }
+}
+
+impl Default for OffsetAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn welford_from_slice(values: &[f64]) -> OffsetAccumulator {
+        let mut acc = OffsetAccumulator::new();
+        for &v in values {
+            acc.update(v);
+        }
+        acc
+    }
+
+    #[test]
+    fn matches_naive_mean_and_variance() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let acc = welford_from_slice(&values);
+
+        let naive_mean: f64 = values.iter().sum::<f64>() / values.len() as f64;
+        let naive_var: f64 = values.iter().map(|v| (v - naive_mean).powi(2)).sum::<f64>()
+            / (values.len() - 1) as f64;
+
+        assert_eq!(acc.n, values.len() as u64);
+        assert!((acc.mean - naive_mean).abs() < 1e-12);
+        assert!((acc.variance() - naive_var).abs() < 1e-12);
+    }
+
+    #[test]
+    fn single_sample_has_zero_variance() {
+        let acc = welford_from_slice(&[42.0]);
+        assert_eq!(acc.n, 1);
+        assert_eq!(acc.mean, 42.0);
+        assert_eq!(acc.variance(), 0.0);
+    }
+
+    #[test]
+    fn empty_accumulator_has_zero_mean_and_variance() {
+        let acc = OffsetAccumulator::new();
+        assert_eq!(acc.n, 0);
+        assert_eq!(acc.mean, 0.0);
+        assert_eq!(acc.variance(), 0.0);
+    }
+
+    #[test]
+    fn merge_matches_combined_stream() {
+        let a_values = [1.0, 2.0, 3.0];
+        let b_values = [10.0, 11.0, 12.0, 13.0];
+
+        let a = welford_from_slice(&a_values);
+        let b = welford_from_slice(&b_values);
+        let merged = a.merge(&b);
+
+        let all: Vec<f64> = a_values.iter().chain(b_values.iter()).copied().collect();
+        let combined = welford_from_slice(&all);
+
+        assert_eq!(merged.n, combined.n);
+        assert!((merged.mean - combined.mean).abs() < 1e-9);
+        assert!((merged.variance() - combined.variance()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn merge_with_empty_accumulator_is_identity() {
+        let acc = welford_from_slice(&[1.0, 2.0, 3.0]);
+        let empty = OffsetAccumulator::new();
+
+        let merged_left = acc.merge(&empty);
+        let merged_right = empty.merge(&acc);
+
+        assert_eq!(merged_left, acc);
+        assert_eq!(merged_right, acc);
+    }
+
+    #[test]
+    fn to_offset_model_seeds_mean_and_variance() {
+        let acc = welford_from_slice(&[0.48, 0.52, 0.5, 0.5]);
+        let model = acc.to_offset_model();
+
+        assert!((model.offset_mean - 0.5).abs() < 1e-2);
+        assert!(model.offset_var > 0.0);
+        assert_eq!(model.drift, 1.0);
+    }
+}
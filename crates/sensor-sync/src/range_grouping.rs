@@ -0,0 +1,243 @@
+//! # Parallele Batch-Gruppierung über einen ganzen Zeitbereich
+//!
+//! Reprocessing langer Aufnahmen über `group_time_slice_probabilistically`
+//! ist ein O(Slices × Beobachtungen) Single-Thread-Loop. Dieses Modul
+//! partitioniert die Slice-Zentren eines Zeitbereichs über einen festen
+//! Thread-Pool, wobei jeder Worker unabhängig die bestehende
+//! Pro-Slice-Gewichtung über seine zugewiesenen Zentren ausführt, und fügt die
+//! Ergebnisse anschließend wieder in Slice-Reihenfolge zusammen — die Ausgabe
+//! ist dadurch unabhängig von der Thread-Anzahl deterministisch. Beobachtungen
+//! werden vorab nach korrigierter Zeit sortiert, damit jeder Worker nur
+//! Beobachtungen in der Nähe seiner Slices betrachtet statt des gesamten
+//! Vektors.
+
+use std::collections::HashMap;
+
+use crate::{group_time_slice_probabilistically, TimeOffset};
+use sensor_redis::{RawObservation, SynchronizedGroup};
+
+/// Grobe Schätzung der korrigierten globalen Start- und Endzeit einer
+/// Beobachtung, nur zum Vorsortieren/-filtern verwendet (nutzt `offset_mean`,
+/// ignoriert Drift und Unsicherheit — die eigentliche Gewichtung bleibt
+/// `group_time_slice_probabilistically` vorbehalten). Instant-Beobachtungen
+/// (`t_local_end` fehlt) liefern `start == end`.
+fn corrected_range_estimate(obs: &RawObservation, offsets: &HashMap<String, TimeOffset>) -> (f64, f64) {
+    let offset_mean = offsets.get(&obs.sensor_id).map_or(0.0, |o| o.offset_mean);
+    let start = obs.t_local + offset_mean;
+    let end = obs.t_local_end.map_or(start, |e| e + offset_mean);
+    if start <= end {
+        (start, end)
+    } else {
+        (end, start)
+    }
+}
+
+/// Sortiere Beobachtungen nach grob geschätzter korrigierter Startzeit, damit
+/// sich die Nachbarschaft eines Slice-Zentrums per Bereichsfilter eingrenzen
+/// lässt.
+fn index_by_corrected_time<'a>(
+    observations: &'a [RawObservation],
+    offsets: &HashMap<String, TimeOffset>,
+) -> Vec<(f64, f64, &'a RawObservation)> {
+    let mut indexed: Vec<(f64, f64, &RawObservation)> = observations
+        .iter()
+        .map(|o| {
+            let (start, end) = corrected_range_estimate(o, offsets);
+            (start, end, o)
+        })
+        .collect();
+    indexed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    indexed
+}
+
+/// Größte im Index vorkommende Spanne (`end - start`), 0.0 falls nur
+/// Instant-Beobachtungen vorliegen.
+fn max_span(indexed: &[(f64, f64, &RawObservation)]) -> f64 {
+    indexed
+        .iter()
+        .map(|(start, end, _)| end - start)
+        .fold(0.0_f64, f64::max)
+}
+
+/// Beobachtungen, deren korrigierte Spanne `[start, end]` `[center - margin,
+/// center + margin]` schneidet, aus dem vorsortierten Index extrahieren
+/// (inklusive Puffer über die Bucket-Nachbarschaft hinaus, da die grobe
+/// Schätzung Drift/Unsicherheit ignoriert).
+///
+/// Da der Index nach Startzeit sortiert ist, eine Intervall-Beobachtung aber
+/// weit vor `center - margin` beginnen und trotzdem bis hinein reichen kann,
+/// wird die binäre Suche zusätzlich um `span` (die größte im Datensatz
+/// vorkommende Spanne) nach links erweitert, statt nur auf `obs.t_local`
+/// allein zu filtern.
+fn observations_near(
+    indexed: &[(f64, f64, &RawObservation)],
+    center: f64,
+    margin: f64,
+    span: f64,
+) -> Vec<RawObservation> {
+    let lo = center - margin;
+    let hi = center + margin;
+    let search_lo = lo - span;
+    let start = indexed.partition_point(|(s, _, _)| *s < search_lo);
+    indexed[start..]
+        .iter()
+        .take_while(|(s, _, _)| *s <= hi)
+        .filter(|(_, e, _)| *e >= lo)
+        .map(|(_, _, o)| (*o).clone())
+        .collect()
+}
+
+/// Gruppiere probabilistisch über jedes Slice-Zentrum in `[t_start, t_end]`
+/// (Schrittweite `step`), parallelisiert über einen festen Thread-Pool.
+///
+/// Liefert die Gruppen in aufsteigender Slice-Reihenfolge, unabhängig von der
+/// tatsächlichen Thread-Anzahl.
+pub fn group_range_probabilistically(
+    t_start: f64,
+    t_end: f64,
+    step: f64,
+    observations: &[RawObservation],
+    offsets: &HashMap<String, TimeOffset>,
+    bucket_size_ms: u64,
+) -> Vec<SynchronizedGroup> {
+    if step <= 0.0 || t_end < t_start {
+        return Vec::new();
+    }
+
+    let mut centers = Vec::new();
+    let mut t = t_start;
+    while t <= t_end + 1e-12 {
+        centers.push(t);
+        t += step;
+    }
+    if centers.is_empty() {
+        return Vec::new();
+    }
+
+    let indexed = index_by_corrected_time(observations, offsets);
+    // Puffer über die Bucket-Nachbarschaft hinaus, um die grobe Zeitschätzung
+    // (ohne Drift/Unsicherheit) auszugleichen.
+    let margin = 2.0 * (bucket_size_ms as f64 / 1000.0) + step;
+    let span = max_span(&indexed);
+
+    let worker_count = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(centers.len());
+    let chunk_size = centers.len().div_ceil(worker_count.max(1));
+
+    let chunks: Vec<&[f64]> = centers.chunks(chunk_size.max(1)).collect();
+
+    let results: Vec<Vec<SynchronizedGroup>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .iter()
+            .map(|&chunk| {
+                let indexed = &indexed;
+                let offsets = &offsets;
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&center| {
+                            let nearby = observations_near(indexed, center, margin, span);
+                            group_time_slice_probabilistically(
+                                center,
+                                &nearby,
+                                offsets,
+                                bucket_size_ms,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(sensor_id: &str, t_local: f64) -> RawObservation {
+        RawObservation {
+            sensor_id: sensor_id.into(),
+            sensor_type: "test".into(),
+            t_local,
+            sigma: 0.05,
+            payload_ref: format!("mem://{sensor_id}"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn long_interval_starting_far_before_center_still_reaches_it() {
+        // Startet weit vor dem Slice-Zentrum (außerhalb von ±margin), die
+        // Spanne deckt das Zentrum aber ab.
+        let mut long_interval = obs("s1", 0.0);
+        long_interval.t_local_end = Some(20.0);
+        let observations = vec![long_interval];
+        let mut offsets = HashMap::new();
+        offsets.insert("s1".to_string(), TimeOffset::new());
+
+        let groups = group_range_probabilistically(10.0, 10.0, 1.0, &observations, &offsets, 1000);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members.len(), 1);
+    }
+
+    #[test]
+    fn covers_every_slice_center_in_range() {
+        let observations = vec![obs("s1", 0.0), obs("s1", 1.0), obs("s1", 2.0), obs("s1", 3.0)];
+        let mut offsets = HashMap::new();
+        offsets.insert("s1".to_string(), TimeOffset::new());
+
+        let groups = group_range_probabilistically(0.0, 3.0, 1.0, &observations, &offsets, 1000);
+        assert_eq!(groups.len(), 4);
+        for (i, group) in groups.iter().enumerate() {
+            assert!((group.t_global - i as f64).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn output_is_identical_regardless_of_observation_count_order() {
+        let mut observations: Vec<RawObservation> = (0..40)
+            .map(|i| obs(&format!("s{i}"), i as f64 * 0.25))
+            .collect();
+        let mut offsets = HashMap::new();
+        for o in &observations {
+            offsets.insert(o.sensor_id.clone(), TimeOffset::new());
+        }
+
+        let forward = group_range_probabilistically(0.0, 10.0, 0.5, &observations, &offsets, 1000);
+
+        observations.reverse();
+        let reversed = group_range_probabilistically(0.0, 10.0, 0.5, &observations, &offsets, 1000);
+
+        assert_eq!(forward.len(), reversed.len());
+        for (a, b) in forward.iter().zip(reversed.iter()) {
+            assert!((a.t_global - b.t_global).abs() < 1e-9);
+            let sum_a: f64 = a.members.iter().map(|m| m.probability).sum();
+            let sum_b: f64 = b.members.iter().map(|m| m.probability).sum();
+            if !a.members.is_empty() {
+                assert!((sum_a - sum_b).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_range_yields_empty_vec() {
+        let observations: Vec<RawObservation> = vec![];
+        let offsets: HashMap<String, TimeOffset> = HashMap::new();
+        let groups = group_range_probabilistically(5.0, 1.0, 1.0, &observations, &offsets, 1000);
+        assert!(groups.is_empty());
+    }
+
+    #[test]
+    fn nonpositive_step_yields_empty_vec() {
+        let observations: Vec<RawObservation> = vec![];
+        let offsets: HashMap<String, TimeOffset> = HashMap::new();
+        let groups = group_range_probabilistically(0.0, 5.0, 0.0, &observations, &offsets, 1000);
+        assert!(groups.is_empty());
+    }
+}
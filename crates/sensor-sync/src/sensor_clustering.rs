@@ -0,0 +1,312 @@
+//! # Hierarchisches Sensor-Clustering nach zeitlicher Kohärenz
+//!
+//! Bisher wird jede `sensor_id` unabhängig behandelt. Dieses Modul entdeckt,
+//! welche Sensoren sich tatsächlich zeitlich gemeinsam bewegen: über einen
+//! Batch von Slice-Stichproben (`SensorTimeSample`) wird eine paarweise zeitliche
+//! Affinitätsmatrix gebaut (inverse gepoolte Varianz der Zeitresiduen, wenn
+//! zwei Sensoren in derselben Slice gemeinsam auftreten) und per
+//! agglomerativem Clustering verschmolzen. Ein Merge wird nur akzeptiert,
+//! wenn die Reduktion der gepoolten Within-Cluster-Varianz einen über ein
+//! Quantil einer Chi-Quadrat-Referenzverteilung gesetzten Schwellwert
+//! überschreitet (über `statrs`). Das Ergebnis ist ein Dendrogramm plus die
+//! flache Cluster-Zuordnung am gewählten Schnitt, damit eng gekoppelte
+//! Sensoren nachgelagert als eine virtuelle Quelle fusioniert werden können.
+
+use std::collections::HashMap;
+
+use statrs::distribution::{ChiSquared, ContinuousCDF};
+
+/// Eine Slice-Stichprobe: für jeden in dieser Slice vorhandenen Sensor dessen
+/// korrigierte effektive Zeit, zusammen mit dem geschätzten `t_global` der
+/// Slice (zur Residuen-Berechnung).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorTimeSample {
+    pub t_global: f64,
+    pub sensor_times: HashMap<String, f64>,
+}
+
+/// Ein Merge-Schritt im Dendrogramm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge {
+    pub left: usize,
+    pub right: usize,
+    /// Index des neu entstandenen Clusters (folgt der üblichen
+    /// scipy-artigen Nummerierung: `n_leaves + merge_index`).
+    pub merged_into: usize,
+    pub variance_reduction: f64,
+}
+
+/// Dendrogramm plus flache Cluster-Zuordnung am Cut.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterResult {
+    pub sensor_ids: Vec<String>,
+    pub merges: Vec<Merge>,
+    /// `cluster_of[i]` ist die Cluster-Zuordnung von `sensor_ids[i]` am
+    /// gewählten Schnitt (flache Partition).
+    pub cluster_of: Vec<usize>,
+}
+
+struct Cluster {
+    members: Vec<usize>,
+    /// Pro Slice-Index gepoolte Residuen-Spur (Mittel der Mitglieder-Residuen).
+    track: HashMap<usize, f64>,
+}
+
+fn residual_variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Gruppiere Sensoren hierarchisch nach zeitlicher Kohärenz.
+///
+/// * `samples` - die Slices, aus denen Residuen (`sensor_time − t_global`)
+///   pro Sensor abgeleitet werden.
+/// * `red_quant` - Quantil (z. B. `0.95`) einer Chi-Quadrat-Referenzverteilung,
+///   das den Cutoff für akzeptierte Varianzreduktionen bestimmt.
+pub fn cluster_sensors_by_coherence(samples: &[SensorTimeSample], red_quant: f64) -> ClusterResult {
+    // Residuen je Sensor über alle Slices sammeln, in denen er auftritt.
+    let mut sensor_ids: Vec<String> = {
+        let mut set: Vec<String> = samples
+            .iter()
+            .flat_map(|s| s.sensor_times.keys().cloned())
+            .collect();
+        set.sort();
+        set.dedup();
+        set
+    };
+    sensor_ids.sort();
+
+    let n = sensor_ids.len();
+    if n == 0 {
+        return ClusterResult {
+            sensor_ids,
+            merges: Vec::new(),
+            cluster_of: Vec::new(),
+        };
+    }
+
+    let index_of: HashMap<&str, usize> = sensor_ids
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.as_str(), i))
+        .collect();
+
+    // Pro Sensor eine Spur: slice_index -> residual.
+    let mut clusters: Vec<Cluster> = (0..n)
+        .map(|i| Cluster {
+            members: vec![i],
+            track: HashMap::new(),
+        })
+        .collect();
+    for (slice_idx, sample) in samples.iter().enumerate() {
+        for (sensor_id, &t) in &sample.sensor_times {
+            let idx = index_of[sensor_id.as_str()];
+            clusters[idx].track.insert(slice_idx, t - sample.t_global);
+        }
+    }
+
+    if n == 1 {
+        return ClusterResult {
+            sensor_ids,
+            merges: Vec::new(),
+            cluster_of: vec![0],
+        };
+    }
+
+    // Chi-Quadrat-Cutoff: Freiheitsgrade aus der gemeinsamen Slice-Anzahl,
+    // mindestens 1.
+    let df = (samples.len().max(1)) as f64;
+    let chi = ChiSquared::new(df).expect("df > 0");
+    let critical = chi.inverse_cdf(red_quant.clamp(1e-6, 1.0 - 1e-9));
+    // Mindestanteil der Varianzreduktion: ein Merge ist nur zulässig, wenn die
+    // gepoolte Varianz um mindestens `critical/df` mal die ungemergte
+    // gewichtete Varianz kleiner wird.
+    let acceptance_ratio = critical / df;
+
+    let mut active: Vec<usize> = (0..n).collect();
+    let mut cluster_id_of_slot: Vec<usize> = (0..n).collect();
+    let mut next_cluster_id = n;
+    let mut merges = Vec::new();
+
+    loop {
+        if active.len() < 2 {
+            break;
+        }
+
+        let mut best: Option<(usize, usize, f64, f64)> = None; // (ai, aj, affinity, reduction)
+        for a in 0..active.len() {
+            for b in (a + 1)..active.len() {
+                let ci = &clusters[active[a]];
+                let cj = &clusters[active[b]];
+
+                let shared: Vec<usize> = ci
+                    .track
+                    .keys()
+                    .filter(|k| cj.track.contains_key(k))
+                    .copied()
+                    .collect();
+                if shared.len() < 2 {
+                    continue;
+                }
+                let diffs: Vec<f64> = shared
+                    .iter()
+                    .map(|k| ci.track[k] - cj.track[k])
+                    .collect();
+                let diff_var = residual_variance(&diffs).max(1e-12);
+                let affinity = 1.0 / diff_var;
+
+                // Baseline: gewichtete mittlere Einzel-Varianz der beiden
+                // Cluster über die gemeinsamen Slices.
+                let vals_i: Vec<f64> = shared.iter().map(|k| ci.track[k]).collect();
+                let vals_j: Vec<f64> = shared.iter().map(|k| cj.track[k]).collect();
+                let baseline = 0.5 * (residual_variance(&vals_i) + residual_variance(&vals_j));
+
+                // Gepoolte Spur des potenziellen Merges: Mittel der beiden
+                // Residuen je gemeinsamer Slice.
+                let pooled: Vec<f64> = shared
+                    .iter()
+                    .map(|k| 0.5 * (ci.track[k] + cj.track[k]))
+                    .collect();
+                let merged_var = residual_variance(&pooled);
+                let reduction = baseline - merged_var;
+
+                if best.map(|(_, _, best_aff, _)| affinity > best_aff).unwrap_or(true) {
+                    best = Some((a, b, affinity, reduction));
+                }
+            }
+        }
+
+        let Some((a, b, _affinity, reduction)) = best else {
+            break;
+        };
+
+        let baseline_scale = {
+            let ci = &clusters[active[a]];
+            let cj = &clusters[active[b]];
+            let shared: Vec<usize> = ci
+                .track
+                .keys()
+                .filter(|k| cj.track.contains_key(k))
+                .copied()
+                .collect();
+            let vals_i: Vec<f64> = shared.iter().map(|k| ci.track[k]).collect();
+            let vals_j: Vec<f64> = shared.iter().map(|k| cj.track[k]).collect();
+            0.5 * (residual_variance(&vals_i) + residual_variance(&vals_j)).max(1e-12)
+        };
+
+        if reduction < acceptance_ratio * baseline_scale {
+            // Beste verbleibende Kandidatenpaarung reduziert die Varianz
+            // nicht ausreichend signifikant: Clustering stoppen.
+            break;
+        }
+
+        let slot_i = active[a];
+        let slot_j = active[b];
+
+        let mut members = clusters[slot_i].members.clone();
+        members.extend(clusters[slot_j].members.iter().copied());
+
+        let keys: Vec<usize> = clusters[slot_i]
+            .track
+            .keys()
+            .chain(clusters[slot_j].track.keys())
+            .copied()
+            .collect();
+        let mut track = HashMap::new();
+        for k in keys {
+            let vi = clusters[slot_i].track.get(&k);
+            let vj = clusters[slot_j].track.get(&k);
+            let v = match (vi, vj) {
+                (Some(x), Some(y)) => 0.5 * (x + y),
+                (Some(x), None) => *x,
+                (None, Some(y)) => *y,
+                (None, None) => continue,
+            };
+            track.insert(k, v);
+        }
+
+        let merged_cluster_id = next_cluster_id;
+        next_cluster_id += 1;
+        merges.push(Merge {
+            left: cluster_id_of_slot[slot_i],
+            right: cluster_id_of_slot[slot_j],
+            merged_into: merged_cluster_id,
+            variance_reduction: reduction,
+        });
+
+        clusters.push(Cluster { members, track });
+        let new_slot = clusters.len() - 1;
+
+        // `a < b`, also erst das größere Slot-Index entfernen.
+        active.remove(b);
+        active.remove(a);
+        active.push(new_slot);
+
+        cluster_id_of_slot.push(merged_cluster_id);
+    }
+
+    let mut cluster_of = vec![0usize; n];
+    for (flat_id, &slot) in active.iter().enumerate() {
+        for &member in &clusters[slot].members {
+            cluster_of[member] = flat_id;
+        }
+    }
+
+    ClusterResult {
+        sensor_ids,
+        merges,
+        cluster_of,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(t_global: f64, entries: &[(&str, f64)]) -> SensorTimeSample {
+        SensorTimeSample {
+            t_global,
+            sensor_times: entries.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        }
+    }
+
+    #[test]
+    fn coherent_sensors_merge_into_one_cluster() {
+        // a und b driften im gleichen Muster relativ zu t_global; c ist unkorreliert.
+        let samples = vec![
+            sample(0.0, &[("a", 0.10), ("b", 0.11), ("c", -0.40)]),
+            sample(1.0, &[("a", 0.12), ("b", 0.13), ("c", 0.35)]),
+            sample(2.0, &[("a", 0.09), ("b", 0.10), ("c", -0.20)]),
+            sample(3.0, &[("a", 0.11), ("b", 0.12), ("c", 0.30)]),
+            sample(4.0, &[("a", 0.10), ("b", 0.11), ("c", -0.10)]),
+        ];
+
+        let result = cluster_sensors_by_coherence(&samples, 0.5);
+        let idx_a = result.sensor_ids.iter().position(|s| s == "a").unwrap();
+        let idx_b = result.sensor_ids.iter().position(|s| s == "b").unwrap();
+        let idx_c = result.sensor_ids.iter().position(|s| s == "c").unwrap();
+
+        assert_eq!(result.cluster_of[idx_a], result.cluster_of[idx_b]);
+        assert_ne!(result.cluster_of[idx_a], result.cluster_of[idx_c]);
+    }
+
+    #[test]
+    fn single_sensor_forms_its_own_cluster() {
+        let samples = vec![sample(0.0, &[("only", 0.1)])];
+        let result = cluster_sensors_by_coherence(&samples, 0.95);
+        assert_eq!(result.sensor_ids, vec!["only".to_string()]);
+        assert_eq!(result.cluster_of, vec![0]);
+        assert!(result.merges.is_empty());
+    }
+
+    #[test]
+    fn empty_samples_yield_empty_result() {
+        let result = cluster_sensors_by_coherence(&[], 0.95);
+        assert!(result.sensor_ids.is_empty());
+        assert!(result.merges.is_empty());
+        assert!(result.cluster_of.is_empty());
+    }
+}
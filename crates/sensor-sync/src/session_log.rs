@@ -0,0 +1,244 @@
+//! # Append-only Session-Log für Beobachtungen und Gruppen
+//!
+//! Bisher existiert keine Persistenz jenseits von Redis: eine Fusionssitzung
+//! lässt sich nicht auf Platte mitschreiben und später erneut durchlaufen.
+//! Dieses Modul schreibt `RawObservation`en und abgeleitete
+//! `SynchronizedGroup`en als newline-delimited JSON in eine Datei, jeweils mit
+//! ihrer korrigierten Zeit als Sortier-/Filterschlüssel. Die erste Zeile der
+//! Datei ist ein Kopf-Datensatz mit dem zum Aufnahmezeitpunkt gültigen
+//! `TimeOffset` je Sensor, damit ein neu geladener Stream dieselbe
+//! Korrektur verwendet wie bei der Aufnahme. So lassen sich lange laufende
+//! Sitzungen langlebig machen und historische Daten mit neuen Kerneln oder
+//! Fenstern neu slicen, ohne die rohen Feeds erneut einzuspielen.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::TimeOffset;
+use sensor_redis::{RawObservation, SynchronizedGroup};
+
+/// Kopf-Datensatz am Anfang jeder Log-Datei.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct SessionHeader {
+    /// Zeit-Offset-Modell je Sensor, wie es zum Aufnahmezeitpunkt galt.
+    pub offsets: HashMap<String, TimeOffset>,
+}
+
+/// Ein einzelner Log-Eintrag: entweder eine Roh-Beobachtung oder eine daraus
+/// abgeleitete Gruppe, jeweils mit ihrer korrigierten Zeit für die
+/// Bereichsabfrage.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionRecord {
+    Observation {
+        corrected_time: f64,
+        observation: RawObservation,
+    },
+    Group {
+        corrected_time: f64,
+        group: SynchronizedGroup,
+    },
+}
+
+impl SessionRecord {
+    pub fn corrected_time(&self) -> f64 {
+        match self {
+            SessionRecord::Observation { corrected_time, .. } => *corrected_time,
+            SessionRecord::Group { corrected_time, .. } => *corrected_time,
+        }
+    }
+}
+
+/// Schreibbares Append-Log. Jede Zeile ist ein JSON-Objekt; die erste Zeile
+/// ist immer der `SessionHeader`.
+pub struct SessionWriter {
+    file: File,
+}
+
+impl SessionWriter {
+    /// Erstelle eine neue Log-Datei (überschreibt eine bestehende gleichen
+    /// Namens) und schreibe den Header.
+    pub fn create(path: impl AsRef<Path>, header: &SessionHeader) -> Result<Self> {
+        let mut file =
+            File::create(path).context("Session-Log konnte nicht erstellt werden")?;
+        writeln!(file, "{}", serde_json::to_string(header)?)?;
+        Ok(Self { file })
+    }
+
+    /// Öffne eine bestehende Log-Datei, um weitere Einträge anzuhängen.
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .context("Session-Log konnte nicht zum Anhängen geöffnet werden")?;
+        Ok(Self { file })
+    }
+
+    /// Hänge eine Beobachtung mit ihrer korrigierten Zeit an.
+    pub fn append_observation(
+        &mut self,
+        corrected_time: f64,
+        observation: RawObservation,
+    ) -> Result<()> {
+        self.append_record(SessionRecord::Observation {
+            corrected_time,
+            observation,
+        })
+    }
+
+    /// Hänge eine abgeleitete Gruppe an (korrigierte Zeit = `group.t_global`).
+    pub fn append_group(&mut self, group: SynchronizedGroup) -> Result<()> {
+        let corrected_time = group.t_global;
+        self.append_record(SessionRecord::Group {
+            corrected_time,
+            group,
+        })
+    }
+
+    fn append_record(&mut self, record: SessionRecord) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(&record)?)?;
+        Ok(())
+    }
+}
+
+/// Lade Header und alle Einträge einer Log-Datei, in der Reihenfolge, in der
+/// sie geschrieben wurden (nicht notwendigerweise zeitlich sortiert).
+pub fn read_session(path: impl AsRef<Path>) -> Result<(SessionHeader, Vec<SessionRecord>)> {
+    let file = File::open(path).context("Session-Log konnte nicht geöffnet werden")?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header_line = lines
+        .next()
+        .context("Session-Log ist leer (kein Header)")??;
+    let header: SessionHeader = serde_json::from_str(&header_line)?;
+
+    let mut records = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line)?);
+    }
+    Ok((header, records))
+}
+
+/// Liefere alle Einträge, deren korrigierte Zeit in `range` liegt, aufsteigend
+/// nach korrigierter Zeit sortiert.
+pub fn query(path: impl AsRef<Path>, range: Range<f64>) -> Result<Vec<SessionRecord>> {
+    let (_, mut records) = read_session(path)?;
+    records.retain(|r| range.contains(&r.corrected_time()));
+    records.sort_by(|a, b| a.corrected_time().total_cmp(&b.corrected_time()));
+    Ok(records)
+}
+
+/// Repliziere alle Einträge einer Log-Datei in chronologischer Reihenfolge
+/// (unabhängig von der Schreibreihenfolge), z. B. um historische Daten mit
+/// einem neuen Kernel oder Fenster neu zu verarbeiten.
+pub fn replay(path: impl AsRef<Path>) -> Result<std::vec::IntoIter<SessionRecord>> {
+    let (_, mut records) = read_session(path)?;
+    records.sort_by(|a, b| a.corrected_time().total_cmp(&b.corrected_time()));
+    Ok(records.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn temp_log_path(name: &str) -> std::path::PathBuf {
+        let mut path = env::temp_dir();
+        path.push(format!("sensor_sync_session_log_test_{name}_{}.jsonl", std::process::id()));
+        path
+    }
+
+    fn obs(sensor_id: &str, t_local: f64) -> RawObservation {
+        RawObservation {
+            sensor_id: sensor_id.into(),
+            sensor_type: "test".into(),
+            t_local,
+            sigma: 0.05,
+            payload_ref: format!("mem://{sensor_id}"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn header_round_trips_through_create_and_read() {
+        let path = temp_log_path("header");
+        let mut offsets = HashMap::new();
+        offsets.insert("s1".to_string(), TimeOffset::with_values(0.5, 0.01, 1.0));
+        let header = SessionHeader { offsets };
+
+        SessionWriter::create(&path, &header).unwrap();
+        let (read_header, records) = read_session(&path).unwrap();
+
+        assert_eq!(read_header, header);
+        assert!(records.is_empty());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn appended_entries_are_read_back_in_write_order() {
+        let path = temp_log_path("append_order");
+        SessionWriter::create(&path, &SessionHeader::default()).unwrap();
+
+        {
+            let mut writer = SessionWriter::append(&path).unwrap();
+            writer.append_observation(5.0, obs("s1", 5.0)).unwrap();
+            writer.append_observation(1.0, obs("s2", 1.0)).unwrap();
+        }
+
+        let (_, records) = read_session(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].corrected_time(), 5.0);
+        assert_eq!(records[1].corrected_time(), 1.0);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn query_filters_and_sorts_by_corrected_time() {
+        let path = temp_log_path("query");
+        SessionWriter::create(&path, &SessionHeader::default()).unwrap();
+        {
+            let mut writer = SessionWriter::append(&path).unwrap();
+            writer.append_observation(9.0, obs("late", 9.0)).unwrap();
+            writer.append_observation(1.0, obs("early", 1.0)).unwrap();
+            writer.append_observation(5.0, obs("mid", 5.0)).unwrap();
+        }
+
+        let results = query(&path, 0.0..6.0).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].corrected_time(), 1.0);
+        assert_eq!(results[1].corrected_time(), 5.0);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn replay_yields_entries_in_chronological_order() {
+        let path = temp_log_path("replay");
+        SessionWriter::create(&path, &SessionHeader::default()).unwrap();
+        {
+            let mut writer = SessionWriter::append(&path).unwrap();
+            writer.append_observation(3.0, obs("c", 3.0)).unwrap();
+            writer.append_observation(1.0, obs("a", 1.0)).unwrap();
+            writer
+                .append_group(SynchronizedGroup {
+                    t_global: 2.0,
+                    members: vec![],
+                })
+                .unwrap();
+        }
+
+        let times: Vec<f64> = replay(&path).unwrap().map(|r| r.corrected_time()).collect();
+        assert_eq!(times, vec![1.0, 2.0, 3.0]);
+        fs::remove_file(&path).ok();
+    }
+}
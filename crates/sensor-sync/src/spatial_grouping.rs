@@ -0,0 +1,369 @@
+//! # Raum-zeitliche Gruppierung über registrierte Sensor-Geometrie
+//!
+//! `group_time_slice_with_kernel` bewertet Mitgliedschaften ausschließlich
+//! über die zeitliche Nähe zu `t_global`. In einem räumlich verteilten
+//! Sensor-Array ist "zeitlich am nächsten" aber nicht dasselbe wie "räumlich
+//! relevant für den Abfragepunkt" — ein Sensor kann exakt zum richtigen
+//! Zeitpunkt messen und trotzdem kilometerweit vom Ereignisort entfernt
+//! stehen. Dieses Modul ergänzt optional registrierte Sensor-Positionen
+//! (`SensorGeometry`) und multipliziert den bestehenden Zeit-Kernel mit einem
+//! räumlichen Kernel über die Distanz zwischen Sensor und Abfragepunkt.
+//! Zusätzlich liefert `coverage` eine Abdeckungsabfrage über die
+//! Sensing-Radien, implementiert per Intervall-Merge über die projizierten
+//! 1D-Reichweiten.
+
+use std::collections::HashMap;
+
+use crate::{bucket_candidate_residual, kernel_weight, TimeOffset, WeightKernel};
+use sensor_redis::{GroupMember, RawObservation, SynchronizedGroup};
+
+/// Distanzmetrik für den räumlichen Kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    Euclidean,
+    Manhattan,
+}
+
+fn distance(metric: DistanceMetric, a: &[f64], b: &[f64]) -> f64 {
+    assert_eq!(a.len(), b.len(), "Positionen müssen gleiche Dimension haben");
+    match metric {
+        DistanceMetric::Euclidean => a
+            .iter()
+            .zip(b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt(),
+        DistanceMetric::Manhattan => a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum(),
+    }
+}
+
+/// Registrierte Positionen und effektive Sensing-Radien je Sensor.
+///
+/// Sensoren ohne Eintrag werden von `group_spatiotemporal` nicht verworfen,
+/// sondern mit räumlichem Gewicht `1.0` behandelt (reine Rückwärtskompatibilität
+/// zu rein zeitlicher Gruppierung).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SensorGeometry {
+    positions: HashMap<String, Vec<f64>>,
+    radii: HashMap<String, f64>,
+}
+
+impl SensorGeometry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registriere die Position und den effektiven Sensing-Radius eines Sensors.
+    /// `position` kann 2D oder 3D (oder beliebig-dimensional) sein, solange
+    /// alle an derselben Abfrage beteiligten Positionen gleich dimensioniert sind.
+    pub fn register(&mut self, sensor_id: &str, position: Vec<f64>, radius: f64) {
+        self.positions.insert(sensor_id.to_string(), position);
+        self.radii.insert(sensor_id.to_string(), radius);
+    }
+
+    pub fn position(&self, sensor_id: &str) -> Option<&[f64]> {
+        self.positions.get(sensor_id).map(|p| p.as_slice())
+    }
+
+    pub fn radius(&self, sensor_id: &str) -> Option<f64> {
+        self.radii.get(sensor_id).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+/// Räumlicher Gauß-Kernel über die Distanz zum Abfragepunkt:
+/// `w = exp(-0.5·(d/spatial_sigma)²)`.
+fn spatial_weight(distance: f64, spatial_sigma: f64) -> f64 {
+    if spatial_sigma <= 0.0 {
+        return if distance.abs() < f64::EPSILON { 1.0 } else { 0.0 };
+    }
+    let z = distance / spatial_sigma;
+    (-0.5 * z * z).exp()
+}
+
+/// Wie `group_time_slice_with_kernel`, aber das Zeitgewicht jeder Beobachtung
+/// wird zusätzlich mit einem räumlichen Gewicht multipliziert: dem
+/// unnormierten Gauß-Kernel `exp(-0.5·(d/spatial_sigma)²)` über die Distanz `d` zwischen der registrierten Position des Sensors und
+/// `query_point` unter `spatial_sigma`. Sensoren ohne registrierte Geometrie
+/// erhalten räumliches Gewicht `1.0` und verhalten sich wie bei rein
+/// zeitlicher Gruppierung.
+#[allow(clippy::too_many_arguments)]
+pub fn group_spatiotemporal(
+    t_global: f64,
+    query_point: &[f64],
+    observations: &[RawObservation],
+    offsets: &HashMap<String, TimeOffset>,
+    geometry: &SensorGeometry,
+    bucket_size_ms: u64,
+    kernel: WeightKernel,
+    spatial_sigma: f64,
+    metric: DistanceMetric,
+) -> SynchronizedGroup {
+    let mut members = Vec::new();
+    let mut weights = Vec::new();
+
+    for obs in observations {
+        let Some(offset) = offsets.get(&obs.sensor_id) else { continue };
+
+        let Some(dt) = bucket_candidate_residual(obs, offset, t_global, bucket_size_ms) else {
+            continue;
+        };
+        let var = offset.offset_variance() + obs.sigma.powi(2);
+        let w_time = kernel_weight(dt, var, kernel);
+
+        let w_space = match geometry.position(&obs.sensor_id) {
+            Some(pos) => spatial_weight(distance(metric, pos, query_point), spatial_sigma),
+            None => 1.0,
+        };
+
+        members.push(GroupMember {
+            sensor_id: obs.sensor_id.clone(),
+            probability: 0.0,
+        });
+        weights.push(w_time * w_space);
+    }
+
+    let sum_w: f64 = weights.iter().copied().sum();
+    if sum_w > 0.0 {
+        for (m, w) in members.iter_mut().zip(weights.into_iter()) {
+            m.probability = w / sum_w;
+        }
+    } else {
+        for m in members.iter_mut() {
+            m.probability = 0.0;
+        }
+    }
+
+    SynchronizedGroup { t_global, members }
+}
+
+/// Verschmelze die Sensing-Intervalle `[pos - radius, pos + radius]` aller
+/// registrierten Sensoren (1D-Projektion auf die erste Koordinate) zu
+/// disjunkten, aufsteigend sortierten Intervallen.
+fn merged_coverage_intervals(geometry: &SensorGeometry) -> Vec<(f64, f64)> {
+    let mut intervals: Vec<(f64, f64)> = geometry
+        .positions
+        .iter()
+        .filter_map(|(sensor_id, pos)| {
+            let radius = geometry.radii.get(sensor_id).copied()?;
+            let center = *pos.first()?;
+            Some((center - radius, center + radius))
+        })
+        .collect();
+    intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f64, f64)> = Vec::new();
+    for (lo, hi) in intervals {
+        match merged.last_mut() {
+            Some((_, last_hi)) if lo <= *last_hi => {
+                *last_hi = last_hi.max(hi);
+            }
+            _ => merged.push((lo, hi)),
+        }
+    }
+    merged
+}
+
+/// Abdeckungsabfrage: für jeden Punkt in `query_points` (entlang derselben
+/// 1D-Achse wie die registrierten Positionen) prüfen, ob er innerhalb der
+/// Reichweite mindestens eines Sensors liegt.
+///
+/// Liefert `(covered, uncovered)`, jeweils in der Reihenfolge von `query_points`.
+pub fn coverage(geometry: &SensorGeometry, query_points: &[f64]) -> (Vec<f64>, Vec<f64>) {
+    let intervals = merged_coverage_intervals(geometry);
+    let mut covered = Vec::new();
+    let mut uncovered = Vec::new();
+    for &point in query_points {
+        let inside = intervals
+            .iter()
+            .any(|&(lo, hi)| point >= lo && point <= hi);
+        if inside {
+            covered.push(point);
+        } else {
+            uncovered.push(point);
+        }
+    }
+    (covered, uncovered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(sensor_id: &str, t_local: f64) -> RawObservation {
+        RawObservation {
+            sensor_id: sensor_id.into(),
+            sensor_type: "test".into(),
+            t_local,
+            sigma: 0.05,
+            payload_ref: format!("mem://{sensor_id}"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn interval_observation_is_kept_when_its_span_covers_t_global() {
+        // Startet weit außerhalb der Bucket-Nachbarschaft von t_global=10.0,
+        // deckt die Spanne aber bis 10.5 ab — darf nicht verworfen werden.
+        let mut long_interval = obs("spanning", 5.0);
+        long_interval.t_local_end = Some(10.5);
+
+        let observations = vec![long_interval];
+        let mut offsets = HashMap::new();
+        offsets.insert("spanning".to_string(), TimeOffset::new());
+
+        let geometry = SensorGeometry::new();
+        let group = group_spatiotemporal(
+            10.0,
+            &[0.0, 0.0],
+            &observations,
+            &offsets,
+            &geometry,
+            1000,
+            WeightKernel::Gaussian,
+            10.0,
+            DistanceMetric::Euclidean,
+        );
+
+        assert_eq!(group.members.len(), 1);
+        assert!((group.members[0].probability - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn closer_sensor_gets_higher_weight_at_equal_time_offset() {
+        let observations = vec![obs("near", 10.0), obs("far", 10.0)];
+        let mut offsets = HashMap::new();
+        offsets.insert("near".to_string(), TimeOffset::new());
+        offsets.insert("far".to_string(), TimeOffset::new());
+
+        let mut geometry = SensorGeometry::new();
+        geometry.register("near", vec![0.0, 0.0], 5.0);
+        geometry.register("far", vec![100.0, 0.0], 5.0);
+
+        let group = group_spatiotemporal(
+            10.0,
+            &[0.0, 0.0],
+            &observations,
+            &offsets,
+            &geometry,
+            1000,
+            WeightKernel::Gaussian,
+            10.0,
+            DistanceMetric::Euclidean,
+        );
+
+        let p_near = group.members.iter().find(|m| m.sensor_id == "near").unwrap().probability;
+        let p_far = group.members.iter().find(|m| m.sensor_id == "far").unwrap().probability;
+        assert!(p_near > p_far);
+    }
+
+    #[test]
+    fn unregistered_sensor_falls_back_to_purely_temporal_weight() {
+        let observations = vec![obs("known", 10.0), obs("unregistered", 10.0)];
+        let mut offsets = HashMap::new();
+        offsets.insert("known".to_string(), TimeOffset::new());
+        offsets.insert("unregistered".to_string(), TimeOffset::new());
+
+        let mut geometry = SensorGeometry::new();
+        geometry.register("known", vec![1000.0, 1000.0], 1.0);
+
+        let group = group_spatiotemporal(
+            10.0,
+            &[0.0, 0.0],
+            &observations,
+            &offsets,
+            &geometry,
+            1000,
+            WeightKernel::Gaussian,
+            10.0,
+            DistanceMetric::Euclidean,
+        );
+
+        let p_known = group.members.iter().find(|m| m.sensor_id == "known").unwrap().probability;
+        let p_unregistered = group
+            .members
+            .iter()
+            .find(|m| m.sensor_id == "unregistered")
+            .unwrap()
+            .probability;
+        assert!(p_unregistered > p_known);
+    }
+
+    #[test]
+    fn manhattan_and_euclidean_agree_on_axis_aligned_distance() {
+        // Der nicht registrierte Sensor (räumliches Gewicht 1.0) macht die
+        // Wahrscheinlichkeit von "a" von seiner Distanz abhängig.
+        let observations = vec![obs("a", 10.0), obs("unregistered", 10.0)];
+        let mut offsets = HashMap::new();
+        offsets.insert("a".to_string(), TimeOffset::new());
+        offsets.insert("unregistered".to_string(), TimeOffset::new());
+
+        let mut geometry = SensorGeometry::new();
+        geometry.register("a", vec![3.0, 0.0], 1.0);
+
+        let euclidean = group_spatiotemporal(
+            10.0,
+            &[0.0, 0.0],
+            &observations,
+            &offsets,
+            &geometry,
+            1000,
+            WeightKernel::Gaussian,
+            5.0,
+            DistanceMetric::Euclidean,
+        );
+        let manhattan = group_spatiotemporal(
+            10.0,
+            &[0.0, 0.0],
+            &observations,
+            &offsets,
+            &geometry,
+            1000,
+            WeightKernel::Gaussian,
+            5.0,
+            DistanceMetric::Manhattan,
+        );
+
+        assert!((euclidean.members[0].probability - manhattan.members[0].probability).abs() < 1e-12);
+    }
+
+    #[test]
+    fn coverage_splits_points_inside_and_outside_sensor_ranges() {
+        let mut geometry = SensorGeometry::new();
+        geometry.register("a", vec![0.0], 2.0);
+        geometry.register("b", vec![10.0], 1.0);
+
+        let (covered, uncovered) = coverage(&geometry, &[0.0, 1.9, 5.0, 9.5, 10.5, 20.0]);
+        assert_eq!(covered, vec![0.0, 1.9, 9.5, 10.5]);
+        assert_eq!(uncovered, vec![5.0, 20.0]);
+    }
+
+    #[test]
+    fn overlapping_sensor_ranges_merge_into_one_interval() {
+        let mut geometry = SensorGeometry::new();
+        geometry.register("a", vec![0.0], 3.0);
+        geometry.register("b", vec![4.0], 3.0);
+
+        // [-3,3] und [1,7] überlappen und werden zu einem Intervall [-3,7]
+        // verschmolzen; 3.5 liegt darin.
+        assert_eq!(merged_coverage_intervals(&geometry), vec![(-3.0, 7.0)]);
+        let (covered, uncovered) = coverage(&geometry, &[3.5]);
+        assert_eq!(covered, vec![3.5]);
+        assert!(uncovered.is_empty());
+    }
+
+    #[test]
+    fn empty_geometry_covers_nothing() {
+        let geometry = SensorGeometry::new();
+        let (covered, uncovered) = coverage(&geometry, &[0.0, 1.0]);
+        assert!(covered.is_empty());
+        assert_eq!(uncovered, vec![0.0, 1.0]);
+    }
+}
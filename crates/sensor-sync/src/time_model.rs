@@ -5,57 +5,121 @@
 //! Zeit-Synchronisation ohne Clock-Synchronisation.
 
 use serde::{Deserialize, Serialize};
+use sensor_redis::TimeSyncState;
+
+/// 95%-Quantil der Chi-Quadrat-Verteilung mit 1 Freiheitsgrad: Standard-Gate
+/// für die normalisierte quadrierte Innovation (NIS) in `kalman_update`.
+pub const DEFAULT_NIS_GATE: f64 = 3.84;
+
+/// Ergebnis eines Kalman-Updates nach NIS-Gating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KalmanUpdateOutcome {
+    /// Messung wurde fusioniert; `nis` ist die normalisierte quadrierte
+    /// Innovation, mit der sie fusioniert wurde.
+    Accepted { nis: f64 },
+    /// Messung wurde verworfen, weil `nis` das Gate überschritten hat (oder
+    /// die Innovationsvarianz nicht positiv war); Zustand bleibt unverändert.
+    MeasurementRejected { nis: f64 },
+}
 
 /// Zeitoffset-Modell mit Kalman-Filter-Zustand.
 ///
-/// Modelliert die Abbildung: t_global = offset_mean + drift * t_local
-/// mit Gaußscher Unsicherheit über offset_mean.
+/// Modelliert die Abbildung: t_global = offset_mean + drift * t_local, mit
+/// vollem 2D-Zustand x = [offset, drift]ᵀ und zugehöriger 2×2-Kovarianz `P`
+/// (anstelle einer skalaren Offset-Varianz), sodass die Drift aus den
+/// Messungen mitgeschätzt wird statt als feste Konstante angenommen zu werden.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TimeOffset {
     /// Erwartungswert des Zeitoffsets (Sekunden)
     pub offset_mean: f64,
-    /// Varianz des Zeitoffsets (Sekunden²)
-    pub offset_variance: f64,
     /// Drift-Faktor (dimensionslos, typischerweise ~1.0)
     pub drift: f64,
+    /// Zustandskovarianz `P` über `[offset, drift]`, symmetrisch:
+    /// `covariance[0][0]` = Offset-Varianz, `covariance[1][1]` = Drift-Varianz,
+    /// `covariance[0][1] == covariance[1][0]` = Kopplung zwischen beiden.
+    covariance: [[f64; 2]; 2],
+    /// Laufendes Mittel der NIS (normalized innovation squared) über alle
+    /// bisherigen `kalman_update`-Aufrufe (akzeptiert wie abgelehnt). Werte
+    /// fern von `1.0` deuten auf ein falsch kalibriertes Rauschmodell hin.
+    #[serde(default)]
+    nis_mean: f64,
+    #[serde(default)]
+    nis_count: u64,
 }
 
 impl TimeOffset {
     /// Erstelle neues TimeOffset-Modell mit Standardwerten.
     ///
     /// # Returns
-    /// TimeOffset mit offset_mean=0, offset_variance=0.1, drift=1.0
+    /// TimeOffset mit offset_mean=0, offset_variance=0.1, drift=1.0, unkorrelierter
+    /// Drift-Varianz 1e-6.
     pub fn new() -> Self {
         Self {
             offset_mean: 0.0,
-            offset_variance: 0.1,
             drift: 1.0,
+            covariance: [[0.1, 0.0], [0.0, 1e-6]],
+            nis_mean: 0.0,
+            nis_count: 0,
         }
     }
 
-    /// Erstelle TimeOffset mit spezifischen Werten.
+    /// Erstelle TimeOffset mit spezifischen Werten (rückwärtskompatibel zum
+    /// rein skalaren Offset-Modell: die Kopplung startet bei 0, die
+    /// Drift-Varianz wie in `new` bei 1e-6).
     pub fn with_values(offset_mean: f64, offset_variance: f64, drift: f64) -> Self {
         Self {
             offset_mean,
-            offset_variance,
             drift,
+            covariance: [[offset_variance, 0.0], [0.0, 1e-6]],
+            nis_mean: 0.0,
+            nis_count: 0,
         }
     }
 
-    /// Kalman-Prädiktion: propagiere Unsicherheit über Zeit.
+    /// Erstelle TimeOffset mit explizitem 2×2-Zustand, inklusive
+    /// Offset-Drift-Kopplung.
+    pub fn with_covariance(offset_mean: f64, drift: f64, covariance: [[f64; 2]; 2]) -> Self {
+        Self {
+            offset_mean,
+            drift,
+            covariance,
+            nis_mean: 0.0,
+            nis_count: 0,
+        }
+    }
+
+    /// Rückwärtskompatibler Zugriff auf die Offset-Varianz `P[0][0]`.
+    pub fn offset_variance(&self) -> f64 {
+        self.covariance[0][0]
+    }
+
+    /// Drift-Varianz `P[1][1]`.
+    pub fn drift_variance(&self) -> f64 {
+        self.covariance[1][1]
+    }
+
+    /// Volle 2×2-Zustandskovarianz.
+    pub fn covariance(&self) -> [[f64; 2]; 2] {
+        self.covariance
+    }
+
+    /// Kalman-Prädiktion: propagiere Zustand und Unsicherheit über Zeit.
     ///
-    /// Erhöht offset_variance um process_noise * dt².
-    /// Verwendet für zeit-korreliertes Rauschen in der Systemdynamik.
+    /// Der Zustand selbst bleibt unverändert (Random-Walk-Modell für Offset
+    /// und Drift); die Kovarianz wächst additiv um ein diagonales
+    /// Prozessrauschen `Q·dt`.
     ///
     /// # Arguments
     /// * `dt` - Zeitdifferenz seit letztem Update (Sekunden)
-    /// * `process_noise` - Prozessrauschen-Intensität (Sekunden²/Sekunde)
-    pub fn predict(&mut self, dt: f64, process_noise: f64) {
-        // Zustandsprädiktion: offset_mean bleibt gleich (konstantes Offset-Modell)
-        // Kovarianzprädiktion: P_k|k-1 = P_k-1 + Q
-        self.offset_variance += process_noise * dt.abs();
-        // Begrenze Varianz nach oben (numerische Stabilität)
-        self.offset_variance = self.offset_variance.min(10.0);
+    /// * `q_offset` - Prozessrauschen-Intensität des Offsets (Sekunden²/Sekunde)
+    /// * `q_drift` - Prozessrauschen-Intensität der Drift (1/Sekunde)
+    pub fn predict(&mut self, dt: f64, q_offset: f64, q_drift: f64) {
+        let dt = dt.abs();
+        self.covariance[0][0] += q_offset * dt;
+        self.covariance[1][1] += q_drift * dt;
+        // Begrenze Varianzen nach oben (numerische Stabilität)
+        self.covariance[0][0] = self.covariance[0][0].min(10.0);
+        self.covariance[1][1] = self.covariance[1][1].min(10.0);
     }
 
     /// Berechne globale Zeit aus lokaler Zeit.
@@ -69,40 +133,133 @@ impl TimeOffset {
         self.offset_mean + self.drift * t_local
     }
 
+    /// Laufendes Mittel der normalisierten quadrierten Innovation (NIS) über
+    /// alle bisherigen `kalman_update`-Aufrufe, akzeptiert wie abgelehnt.
+    /// Für ein korrekt kalibriertes Rauschmodell liegt der Erwartungswert
+    /// nahe `1.0` (1 Freiheitsgrad); deutlich größere Werte deuten auf zu
+    /// optimistisch angenommenes Rauschen hin, deutlich kleinere auf zu
+    /// pessimistisches.
+    pub fn running_average_nis(&self) -> f64 {
+        self.nis_mean
+    }
+
+    /// Anzahl der `kalman_update`-Aufrufe, die in `running_average_nis`
+    /// eingeflossen sind.
+    pub fn nis_sample_count(&self) -> u64 {
+        self.nis_count
+    }
+
+    /// Trage einen neuen NIS-Wert in das laufende Mittel ein (inkrementelle
+    /// Mittelwertbildung, numerisch stabil auch für lange Sitzungen).
+    fn record_nis(&mut self, nis: f64) {
+        self.nis_count += 1;
+        self.nis_mean += (nis - self.nis_mean) / self.nis_count as f64;
+    }
+
     /// Kalman-Update mit einer neuen Zeitmessung.
     ///
-    /// Fusioniert Vorhersage mit Messung via optimaler Kalman-Gain.
+    /// Rückwärtskompatible Variante von [`Self::kalman_update_gated`] mit dem
+    /// Standard-Gate [`DEFAULT_NIS_GATE`]; das Ergebnis wird verworfen, wenn
+    /// der Aufrufer es nicht braucht.
     ///
     /// # Arguments
     /// * `measurement` - Gemessene globale Zeit (Sekunden)
     /// * `measurement_variance` - Messunsicherheit (Sekunden²)
     /// * `t_local` - Lokaler Zeitstempel der Messung (Sekunden)
     pub fn kalman_update(&mut self, measurement: f64, measurement_variance: f64, t_local: f64) {
-        // Messung vorhersagen
-        let predicted = self.predict_global_time(t_local);
-        
+        self.kalman_update_gated(measurement, measurement_variance, t_local, DEFAULT_NIS_GATE);
+    }
+
+    /// Kalman-Update mit Chi-Quadrat-Gating auf der normalisierten
+    /// quadrierten Innovation (NIS), um Ausreißer-Messungen zu verwerfen statt
+    /// sie in den Zustand zu fusionieren.
+    ///
+    /// Messmodell `z = H·x` mit `H = [1, t_local]`, sodass die vorhergesagte
+    /// globale Zeit weiterhin `offset + drift·t_local` ist. Kovarianz-Update
+    /// in Joseph-Form `(I-K·H)·P·(I-K·H)ᵀ + K·R·Kᵀ`, um `P` auch bei
+    /// numerischen Rundungsfehlern symmetrisch positiv-definit zu halten.
+    /// `nis = innovation² / innovation_variance` ist Chi-Quadrat-verteilt mit
+    /// 1 Freiheitsgrad; überschreitet sie `gate`, wird die Messung verworfen
+    /// und der Zustand bleibt unverändert. Die NIS fließt unabhängig vom
+    /// Ausgang in `running_average_nis` ein, damit ein dauerhaft von `1.0`
+    /// abweichendes Mittel ein falsch kalibriertes Rauschmodell anzeigt.
+    ///
+    /// # Arguments
+    /// * `measurement` - Gemessene globale Zeit (Sekunden)
+    /// * `measurement_variance` - Messunsicherheit (Sekunden²)
+    /// * `t_local` - Lokaler Zeitstempel der Messung (Sekunden)
+    /// * `gate` - Maximal zulässige NIS, ab der eine Messung verworfen wird
+    pub fn kalman_update_gated(
+        &mut self,
+        measurement: f64,
+        measurement_variance: f64,
+        t_local: f64,
+        gate: f64,
+    ) -> KalmanUpdateOutcome {
+        let h = [1.0, t_local];
+        let p = self.covariance;
+
         // Innovation (Residuum)
+        let predicted = self.predict_global_time(t_local);
         let innovation = measurement - predicted;
-        
-        // Innovations-Kovarianz: S = H P H^T + R
-        // Bei linearem Messmodell H=1: S = P + R
-        let innovation_variance = self.offset_variance + measurement_variance;
-        
-        // Kalman-Gain: K = P H^T S^-1
+
+        // Innovations-Varianz: S = H P H^T + R (Skalar)
+        let p_h = [
+            p[0][0] * h[0] + p[0][1] * h[1],
+            p[1][0] * h[0] + p[1][1] * h[1],
+        ];
+        let h_p_ht = h[0] * p_h[0] + h[1] * p_h[1];
+        let innovation_variance = h_p_ht + measurement_variance;
+
         if innovation_variance <= 0.0 {
-            // Keine Information: Skip Update
-            return;
+            // Keine Information: Skip Update, aber als Ausreißer werten.
+            let nis = f64::INFINITY;
+            self.record_nis(nis);
+            return KalmanUpdateOutcome::MeasurementRejected { nis };
+        }
+
+        let nis = (innovation * innovation) / innovation_variance;
+        self.record_nis(nis);
+        if nis > gate {
+            return KalmanUpdateOutcome::MeasurementRejected { nis };
         }
-        let kalman_gain = self.offset_variance / innovation_variance;
-        
+
+        // Kalman-Gain: K = P H^T / S (2x1-Vektor)
+        let k = [p_h[0] / innovation_variance, p_h[1] / innovation_variance];
+
         // Zustandsupdate: x = x + K * innovation
-        self.offset_mean += kalman_gain * innovation;
-        
-        // Kovarianzupdate: P = (I - K H) P = (1 - K) P
-        self.offset_variance = (1.0 - kalman_gain) * self.offset_variance;
-        
-        // Begrenze Varianz nach unten (numerische Stabilität, verhindere Überkonfidenz)
-        self.offset_variance = self.offset_variance.max(1e-6);
+        self.offset_mean += k[0] * innovation;
+        self.drift += k[1] * innovation;
+
+        // Kovarianzupdate in Joseph-Form: P = (I-KH) P (I-KH)^T + K R K^T
+        let ikh = [
+            [1.0 - k[0] * h[0], -k[0] * h[1]],
+            [-k[1] * h[0], 1.0 - k[1] * h[1]],
+        ];
+        let mut ikh_p = [[0.0; 2]; 2];
+        for i in 0..2 {
+            for j in 0..2 {
+                ikh_p[i][j] = ikh[i][0] * p[0][j] + ikh[i][1] * p[1][j];
+            }
+        }
+        let mut new_p = [[0.0; 2]; 2];
+        for i in 0..2 {
+            for j in 0..2 {
+                new_p[i][j] = ikh_p[i][0] * ikh[j][0] + ikh_p[i][1] * ikh[j][1]
+                    + k[i] * measurement_variance * k[j];
+            }
+        }
+        // Rundungsbedingte Asymmetrie der Nebendiagonale beseitigen.
+        let off_diagonal = 0.5 * (new_p[0][1] + new_p[1][0]);
+        new_p[0][1] = off_diagonal;
+        new_p[1][0] = off_diagonal;
+
+        // Begrenze Varianzen nach unten (numerische Stabilität, verhindere Überkonfidenz)
+        new_p[0][0] = new_p[0][0].max(1e-6);
+        new_p[1][1] = new_p[1][1].max(1e-9);
+        self.covariance = new_p;
+
+        KalmanUpdateOutcome::Accepted { nis }
     }
 }
 
@@ -112,6 +269,33 @@ impl Default for TimeOffset {
     }
 }
 
+impl From<&TimeOffset> for TimeSyncState {
+    fn from(value: &TimeOffset) -> Self {
+        Self {
+            offset_mean: value.offset_mean,
+            offset_var: value.offset_variance(),
+            drift: value.drift,
+            drift_var: value.drift_variance(),
+            offset_drift_covariance: value.covariance[0][1],
+        }
+    }
+}
+
+impl From<&TimeSyncState> for TimeOffset {
+    fn from(value: &TimeSyncState) -> Self {
+        Self {
+            offset_mean: value.offset_mean,
+            drift: value.drift,
+            covariance: [
+                [value.offset_var, value.offset_drift_covariance],
+                [value.offset_drift_covariance, value.drift_var],
+            ],
+            nis_mean: 0.0,
+            nis_count: 0,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,7 +304,7 @@ mod tests {
     fn new_creates_default_values() {
         let offset = TimeOffset::new();
         assert_eq!(offset.offset_mean, 0.0);
-        assert_eq!(offset.offset_variance, 0.1);
+        assert_eq!(offset.offset_variance(), 0.1);
         assert_eq!(offset.drift, 1.0);
     }
 
@@ -142,10 +326,18 @@ mod tests {
     #[test]
     fn predict_increases_variance() {
         let mut offset = TimeOffset::with_values(0.0, 0.01, 1.0);
-        let initial_var = offset.offset_variance;
-        offset.predict(1.0, 0.001);
-        assert!(offset.offset_variance > initial_var);
-        assert!((offset.offset_variance - 0.011).abs() < 1e-9);
+        let initial_var = offset.offset_variance();
+        offset.predict(1.0, 0.001, 0.0);
+        assert!(offset.offset_variance() > initial_var);
+        assert!((offset.offset_variance() - 0.011).abs() < 1e-9);
+    }
+
+    #[test]
+    fn predict_also_grows_drift_variance() {
+        let mut offset = TimeOffset::new();
+        let initial_drift_var = offset.drift_variance();
+        offset.predict(1.0, 0.0, 1e-4);
+        assert!(offset.drift_variance() > initial_drift_var);
     }
 
     #[test]
@@ -153,37 +345,53 @@ mod tests {
         let mut offset = TimeOffset::new();
         let true_offset = 0.5;
         let measurement_var = 0.01;
-        
+
         // Simuliere 10 Messungen mit wahrem Offset 0.5
         for i in 1..=10 {
             let t_local = i as f64;
             let measurement = true_offset + t_local; // true_global = true_offset + 1.0 * t_local
             offset.kalman_update(measurement, measurement_var, t_local);
         }
-        
+
         // Offset sollte gegen wahren Wert konvergieren
         assert!((offset.offset_mean - true_offset).abs() < 0.05);
         // Varianz sollte abnehmen
-        assert!(offset.offset_variance < 0.1);
+        assert!(offset.offset_variance() < 0.1);
+    }
+
+    #[test]
+    fn kalman_update_learns_nonunit_drift() {
+        let mut offset = TimeOffset::new();
+        let true_offset = 0.2;
+        let true_drift = 1.001;
+
+        for i in 1..=50 {
+            let t_local = i as f64;
+            let measurement = true_offset + true_drift * t_local;
+            offset.kalman_update(measurement, 1e-6, t_local);
+        }
+
+        assert!((offset.drift - true_drift).abs() < 1e-3);
+        assert!((offset.offset_mean - true_offset).abs() < 0.05);
     }
 
     #[test]
     fn kalman_update_with_noisy_measurements() {
         let mut offset = TimeOffset::new();
         let true_offset = 0.3;
-        
+
         // Messungen mit simuliertem Rauschen
         let measurements = vec![
-            (1.0, 1.32, 0.02),  // (t_local, t_global_measured, variance)
+            (1.0, 1.32, 0.02), // (t_local, t_global_measured, variance)
             (2.0, 2.28, 0.02),
             (3.0, 3.31, 0.02),
             (4.0, 4.29, 0.02),
         ];
-        
+
         for (t_local, measurement, var) in measurements {
             offset.kalman_update(measurement, var, t_local);
         }
-        
+
         // Trotz Rauschen sollte Schätzung plausibel sein
         assert!((offset.offset_mean - true_offset).abs() < 0.1);
     }
@@ -191,14 +399,14 @@ mod tests {
     #[test]
     fn predict_and_update_cycle() {
         let mut offset = TimeOffset::with_values(0.1, 0.05, 1.0);
-        
+
         // Predict-Update-Zyklus
-        offset.predict(1.0, 0.001);
-        let var_after_predict = offset.offset_variance;
-        
+        offset.predict(1.0, 0.001, 0.0);
+        let var_after_predict = offset.offset_variance();
+
         offset.kalman_update(10.2, 0.01, 10.0);
-        let var_after_update = offset.offset_variance;
-        
+        let var_after_update = offset.offset_variance();
+
         // Predict erhöht Varianz, Update reduziert sie
         assert!(var_after_predict > 0.05);
         assert!(var_after_update < var_after_predict);
@@ -207,17 +415,111 @@ mod tests {
     #[test]
     fn variance_bounds_enforced() {
         let mut offset = TimeOffset::new();
-        
+
         // Test untere Grenze
         offset.kalman_update(10.0, 1e-12, 10.0);
         offset.kalman_update(20.0, 1e-12, 20.0);
-        assert!(offset.offset_variance >= 1e-6);
-        
+        assert!(offset.offset_variance() >= 1e-6);
+
         // Test obere Grenze
         let mut offset2 = TimeOffset::new();
         for _ in 0..1000 {
-            offset2.predict(1.0, 1.0);
+            offset2.predict(1.0, 1.0, 1.0);
+        }
+        assert!(offset2.offset_variance() <= 10.0);
+        assert!(offset2.drift_variance() <= 10.0);
+    }
+
+    #[test]
+    fn time_sync_state_round_trip_preserves_full_covariance() {
+        let mut offset = TimeOffset::new();
+        for i in 1..=20 {
+            let t_local = i as f64;
+            offset.kalman_update(0.5 + 1.0002 * t_local, 0.01, t_local);
+        }
+
+        let state: TimeSyncState = (&offset).into();
+        let round_tripped: TimeOffset = (&state).into();
+
+        assert!((round_tripped.offset_mean - offset.offset_mean).abs() < 1e-12);
+        assert!((round_tripped.drift - offset.drift).abs() < 1e-12);
+        assert_eq!(round_tripped.covariance(), offset.covariance());
+    }
+
+    #[test]
+    fn covariance_stays_symmetric_after_updates() {
+        let mut offset = TimeOffset::new();
+        for i in 1..=20 {
+            let t_local = i as f64;
+            offset.kalman_update(0.5 + 1.0002 * t_local, 0.01, t_local);
+        }
+        let p = offset.covariance();
+        assert!((p[0][1] - p[1][0]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gated_update_accepts_consistent_measurement() {
+        let mut offset = TimeOffset::with_values(0.0, 0.01, 1.0);
+        let outcome = offset.kalman_update_gated(1.01, 0.01, 1.0, DEFAULT_NIS_GATE);
+        assert!(matches!(outcome, KalmanUpdateOutcome::Accepted { .. }));
+    }
+
+    #[test]
+    fn gated_update_rejects_wild_outlier_and_leaves_state_unchanged() {
+        let mut offset = TimeOffset::with_values(0.0, 0.01, 1.0);
+        let before_mean = offset.offset_mean;
+        let before_drift = offset.drift;
+        let before_cov = offset.covariance();
+
+        // Wahrer Offset liegt nahe 0, diese Messung ist um Größenordnungen
+        // daneben -> sollte als Ausreißer verworfen werden.
+        let outcome = offset.kalman_update_gated(500.0, 0.01, 1.0, DEFAULT_NIS_GATE);
+
+        match outcome {
+            KalmanUpdateOutcome::MeasurementRejected { nis } => assert!(nis > DEFAULT_NIS_GATE),
+            KalmanUpdateOutcome::Accepted { .. } => panic!("expected rejection"),
+        }
+        assert_eq!(offset.offset_mean, before_mean);
+        assert_eq!(offset.drift, before_drift);
+        assert_eq!(offset.covariance(), before_cov);
+    }
+
+    #[test]
+    fn rejected_measurement_still_counts_toward_running_average_nis() {
+        let mut offset = TimeOffset::with_values(0.0, 0.01, 1.0);
+        assert_eq!(offset.nis_sample_count(), 0);
+        offset.kalman_update_gated(500.0, 0.01, 1.0, DEFAULT_NIS_GATE);
+        assert_eq!(offset.nis_sample_count(), 1);
+        assert!(offset.running_average_nis() > DEFAULT_NIS_GATE);
+    }
+
+    #[test]
+    fn running_average_nis_stays_near_one_for_well_calibrated_noise() {
+        let mut offset = TimeOffset::new();
+        let true_offset = 0.1;
+
+        // Deterministisch um die gemeldete Messvarianz oszillierende
+        // Residuen simulieren ein korrekt kalibriertes Rauschmodell.
+        for i in 1..=40 {
+            let t_local = i as f64;
+            let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+            offset.kalman_update(true_offset + t_local + noise, 1e-4, t_local);
         }
-        assert!(offset2.offset_variance <= 10.0);
+
+        assert_eq!(offset.nis_sample_count(), 40);
+        assert!(offset.running_average_nis() < DEFAULT_NIS_GATE);
+    }
+
+    #[test]
+    fn default_kalman_update_applies_default_gate() {
+        let mut gated = TimeOffset::with_values(0.0, 0.01, 1.0);
+        let mut default_path = TimeOffset::with_values(0.0, 0.01, 1.0);
+
+        gated.kalman_update_gated(500.0, 0.01, 1.0, DEFAULT_NIS_GATE);
+        default_path.kalman_update(500.0, 0.01, 1.0);
+
+        assert_eq!(gated.offset_mean, default_path.offset_mean);
+        assert_eq!(gated.drift, default_path.drift);
+        assert_eq!(gated.covariance(), default_path.covariance());
     }
 }